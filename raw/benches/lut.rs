@@ -0,0 +1,152 @@
+//! 24MP 图像上的 lut 插值耗时
+//!
+//! nested_tetrahedral 为改为连续存放之前的实现（Vec<Vec<Vec<_>>> 存储），作为对照
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rayon::prelude::*;
+use raw::{Lut, LutFormat, LutInterpolation, LutLoader};
+
+const WIDTH: usize = 6000;
+const HEIGHT: usize = 4000;
+const LUT_SIZE: usize = 33;
+
+/// 任意的非线性色彩变换，用于生成测试 lut
+fn transform(r: f32, g: f32, b: f32) -> [f32; 3] {
+    [
+        (r * 0.9 + g * 0.1).powf(0.8),
+        (g * 0.8 + b * 0.2).powf(1.1),
+        (b * 0.85 + r * 0.15).sqrt(),
+    ]
+}
+
+fn cube_text() -> String {
+    let scale = (LUT_SIZE - 1) as f32;
+    let mut text = format!("LUT_3D_SIZE {}\n", LUT_SIZE);
+    // .cube 中红色变化最快
+    for b in 0..LUT_SIZE {
+        for g in 0..LUT_SIZE {
+            for r in 0..LUT_SIZE {
+                let [r, g, b] = transform(r as f32 / scale, g as f32 / scale, b as f32 / scale);
+                text.push_str(&format!("{} {} {}\n", r, g, b));
+            }
+        }
+    }
+    text
+}
+
+/// 平滑渐变加少量噪声，接近照片中相邻像素取值相近的情况
+fn image<T>(channels: usize, max: f32, from: impl Fn(f32) -> T) -> Vec<T> {
+    let mut state = 0x2545_f491_u32;
+    let mut noise = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        (state % 1024) as f32 / 1024.0 * 0.02
+    };
+    let mut data = Vec::with_capacity(WIDTH * HEIGHT * channels);
+    for y in 0..HEIGHT {
+        let v = y as f32 / HEIGHT as f32;
+        for x in 0..WIDTH {
+            let u = x as f32 / WIDTH as f32;
+            let rgb = [u, v, (u * 7.0).sin() * (v * 5.0).cos() * 0.5 + 0.5];
+            for c in 0..channels {
+                let value = rgb.get(c).map_or(1.0, |&value| (value + noise()).min(1.0));
+                data.push(from(value * max));
+            }
+        }
+    }
+    data
+}
+
+struct NestedLut {
+    lut: Vec<Vec<Vec<[f32; 3]>>>,
+    size: usize,
+}
+
+impl NestedLut {
+    fn new() -> Self {
+        let scale = (LUT_SIZE - 1) as f32;
+        let lut = (0..LUT_SIZE)
+            .map(|r| {
+                (0..LUT_SIZE)
+                    .map(|g| {
+                        (0..LUT_SIZE)
+                            .map(|b| transform(r as f32 / scale, g as f32 / scale, b as f32 / scale))
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        NestedLut { lut, size: LUT_SIZE }
+    }
+
+    fn tetrahedral(&self, s: [f32; 3]) -> [f32; 3] {
+        let prev = s.map(|v| v as usize);
+        let next = prev.map(|v| (v + 1).min(self.size - 1));
+        let [r, g, b] = std::array::from_fn(|c| s[c] - prev[c] as f32);
+        let at = |i: [usize; 3]| self.lut[i[0]][i[1]][i[2]];
+        let c000 = at(prev);
+        let c111 = at(next);
+        let (w, c1, c2) = if r > g {
+            if g > b {
+                ([1.0 - r, r - g, g - b, b], at([next[0], prev[1], prev[2]]), at([next[0], next[1], prev[2]]))
+            } else if r > b {
+                ([1.0 - r, r - b, b - g, g], at([next[0], prev[1], prev[2]]), at([next[0], prev[1], next[2]]))
+            } else {
+                ([1.0 - b, b - r, r - g, g], at([prev[0], prev[1], next[2]]), at([next[0], prev[1], next[2]]))
+            }
+        } else if b > g {
+            ([1.0 - b, b - g, g - r, r], at([prev[0], prev[1], next[2]]), at([prev[0], next[1], next[2]]))
+        } else if b > r {
+            ([1.0 - g, g - b, b - r, r], at([prev[0], next[1], prev[2]]), at([prev[0], next[1], next[2]]))
+        } else {
+            ([1.0 - g, g - r, r - b, b], at([prev[0], next[1], prev[2]]), at([next[0], next[1], prev[2]]))
+        };
+        std::array::from_fn(|c| w[0] * c000[c] + w[1] * c1[c] + w[2] * c2[c] + w[3] * c111[c])
+    }
+
+    fn apply(&self, indata: &[u16]) -> Vec<u16> {
+        let scale = (self.size - 1) as f32;
+        let mut outdata = vec![0; indata.len()];
+        outdata
+            .par_chunks_mut(WIDTH * 3)
+            .zip(indata.par_chunks(WIDTH * 3))
+            .for_each(|(out, row)| {
+                for (o, p) in out.chunks_exact_mut(3).zip(row.chunks_exact(3)) {
+                    let rgb = self.tetrahedral(std::array::from_fn(|c| p[c] as f32 / 65535.0 * scale));
+                    for c in 0..3 {
+                        o[c] = (rgb[c] * 65535.0).round().clamp(0.0, 65535.0) as u16;
+                    }
+                }
+            });
+        outdata
+    }
+}
+
+fn bench_lut(c: &mut Criterion) {
+    let lut: Lut = LutLoader::parse(cube_text().as_bytes(), LutFormat::Cube).unwrap();
+    let nested = NestedLut::new();
+    let rgb16 = image(3, u16::MAX as f32, |v| v as u16);
+    let rgba8 = image(4, u8::MAX as f32, |v| v as u8);
+
+    let mut group = c.benchmark_group("lut_24mp");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((WIDTH * HEIGHT) as u64));
+    group.bench_function("nested_tetrahedral_rgb16", |b| b.iter(|| nested.apply(&rgb16)));
+    for (name, interpolation) in [
+        ("nearest", LutInterpolation::Nearest),
+        ("trilinear", LutInterpolation::Trilinear),
+        ("tetrahedral", LutInterpolation::Tetrahedral),
+    ] {
+        group.bench_function(format!("{}_rgb16", name), |b| {
+            b.iter(|| lut.apply(&rgb16, WIDTH, 3, interpolation, 1.0))
+        });
+    }
+    group.bench_function("tetrahedral_rgba8", |b| {
+        b.iter(|| lut.apply(&rgba8, WIDTH, 4, LutInterpolation::Tetrahedral, 1.0))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_lut);
+criterion_main!(benches);
@@ -0,0 +1,140 @@
+use rayon::prelude::*;
+
+use crate::options::Adjustments;
+
+/// Rec.709 亮度权重
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// 按固定顺序应用色调调整：黑白场、阴影高光、对比度、色调曲线，最后是自然饱和度和饱和度
+pub(crate) fn apply_adjustments(data: &mut [u16], adjustments: &Adjustments) {
+    if adjustments.is_identity() {
+        return;
+    }
+    let lut = tone_lut(adjustments);
+    let vibrance = adjustments.vibrance.clamp(-100.0, 100.0) / 100.0;
+    let saturation = adjustments.saturation.clamp(-100.0, 100.0) / 100.0;
+
+    data.par_chunks_exact_mut(3).for_each(|p| {
+        let mut rgb = [0, 1, 2].map(|c| lut[p[c] as usize] as f32 / 65535.0);
+        if vibrance != 0.0 {
+            // 饱和度越低的像素调整幅度越大，避免已经鲜艳的颜色溢出
+            let max = rgb[0].max(rgb[1]).max(rgb[2]);
+            let min = rgb[0].min(rgb[1]).min(rgb[2]);
+            let sat = if max > 0.0 { (max - min) / max } else { 0.0 };
+            rgb = scale_saturation(rgb, vibrance * (1.0 - sat));
+        }
+        if saturation != 0.0 {
+            rgb = scale_saturation(rgb, saturation);
+        }
+        for c in 0..3 {
+            p[c] = (rgb[c].clamp(0.0, 1.0) * 65535.0).round() as u16;
+        }
+    });
+}
+
+fn scale_saturation(rgb: [f32; 3], amount: f32) -> [f32; 3] {
+    let luma = rgb[0] * LUMA[0] + rgb[1] * LUMA[1] + rgb[2] * LUMA[2];
+    rgb.map(|v| luma + (v - luma) * (1.0 + amount))
+}
+
+/// 将亮度相关的调整合并为一张 16 位查找表
+fn tone_lut(adjustments: &Adjustments) -> Vec<u16> {
+    let amount = |v: f32| v.clamp(-100.0, 100.0) / 100.0;
+    let blacks = amount(adjustments.blacks);
+    let whites = amount(adjustments.whites);
+    let shadows = amount(adjustments.shadows);
+    let highlights = amount(adjustments.highlights);
+    let contrast = amount(adjustments.contrast);
+    let curve = ToneCurve::new(&adjustments.tone_curve);
+    // 黑白场最多移动 10%
+    let black_point = -0.1 * blacks;
+    let white_point = 1.0 - 0.1 * whites;
+
+    (0..=u16::MAX)
+        .into_par_iter()
+        .map(|i| {
+            let mut x = i as f32 / 65535.0;
+            x = ((x - black_point) / (white_point - black_point)).clamp(0.0, 1.0);
+            // 阴影和高光的权重分别在 1/3 和 2/3 处最大，系数保证曲线单调
+            x += shadows * x * (1.0 - x) * (1.0 - x);
+            x += highlights * x * x * (1.0 - x);
+            x += contrast * (x * x * (3.0 - 2.0 * x) - x);
+            if let Some(curve) = &curve {
+                x = curve.eval(x);
+            }
+            (x.clamp(0.0, 1.0) * 65535.0).round() as u16
+        })
+        .collect()
+}
+
+/// 经过控制点的单调三次插值曲线（Fritsch-Carlson）
+struct ToneCurve {
+    xs: Vec<f32>,
+    ys: Vec<f32>,
+    tangents: Vec<f32>,
+}
+
+impl ToneCurve {
+    /// 控制点少于两个时返回空
+    fn new(points: &[(f32, f32)]) -> Option<Self> {
+        let mut points: Vec<(f32, f32)> = points
+            .iter()
+            .map(|&(x, y)| (x.clamp(0.0, 1.0), y.clamp(0.0, 1.0)))
+            .collect();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        points.dedup_by(|a, b| a.0 == b.0);
+        if points.len() < 2 {
+            return None;
+        }
+        let (xs, ys): (Vec<f32>, Vec<f32>) = points.into_iter().unzip();
+        let n = xs.len();
+        let slopes: Vec<f32> = (0..n - 1)
+            .map(|i| (ys[i + 1] - ys[i]) / (xs[i + 1] - xs[i]))
+            .collect();
+        let mut tangents = vec![0.0; n];
+        tangents[0] = slopes[0];
+        tangents[n - 1] = slopes[n - 2];
+        for i in 1..n - 1 {
+            tangents[i] = if slopes[i - 1] * slopes[i] <= 0.0 {
+                0.0
+            } else {
+                (slopes[i - 1] + slopes[i]) / 2.0
+            };
+        }
+        for i in 0..n - 1 {
+            if slopes[i] == 0.0 {
+                tangents[i] = 0.0;
+                tangents[i + 1] = 0.0;
+                continue;
+            }
+            let a = tangents[i] / slopes[i];
+            let b = tangents[i + 1] / slopes[i];
+            let h = a * a + b * b;
+            if h > 9.0 {
+                let t = 3.0 / h.sqrt();
+                tangents[i] = t * a * slopes[i];
+                tangents[i + 1] = t * b * slopes[i];
+            }
+        }
+        Some(ToneCurve { xs, ys, tangents })
+    }
+
+    /// 控制点范围之外保持端点的值
+    fn eval(&self, x: f32) -> f32 {
+        let n = self.xs.len();
+        if x <= self.xs[0] {
+            return self.ys[0];
+        }
+        if x >= self.xs[n - 1] {
+            return self.ys[n - 1];
+        }
+        let i = self.xs.partition_point(|&v| v <= x) - 1;
+        let h = self.xs[i + 1] - self.xs[i];
+        let t = (x - self.xs[i]) / h;
+        let (t2, t3) = (t * t, t * t * t);
+        (2.0 * t3 - 3.0 * t2 + 1.0) * self.ys[i]
+            + (t3 - 2.0 * t2 + t) * h * self.tangents[i]
+            + (-2.0 * t3 + 3.0 * t2) * self.ys[i + 1]
+            + (t3 - t2) * h * self.tangents[i + 1]
+    }
+}
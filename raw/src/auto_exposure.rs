@@ -0,0 +1,94 @@
+use rayon::prelude::*;
+
+use crate::options::{ColorSpace, Metering};
+use crate::tone_map::decode;
+
+/// 直方图分箱数
+const BINS: usize = 1024;
+/// 中间调目标，18% 灰
+const MID_TARGET: f32 = 0.18;
+/// 提升曝光时高光百分位不超过该线性亮度，留出少量余量避免溢出
+const HIGHLIGHT_TARGET: f32 = 0.9;
+const MID_PERCENTILE: f32 = 0.5;
+const HIGHLIGHT_PERCENTILE: f32 = 0.99;
+/// 可用的曝光补偿范围，与手动曝光一致
+pub(crate) const EV_RANGE: (f32, f32) = (-2.0, 3.0);
+
+/// 根据亮度直方图计算曝光补偿，单位 EV
+///
+/// 中间调百分位对齐 18% 灰，同时限制提亮幅度使高光百分位不溢出
+pub(crate) fn auto_exposure_ev(
+    data: &[u16],
+    width: usize,
+    height: usize,
+    metering: Metering,
+    color_space: ColorSpace,
+) -> f32 {
+    if width == 0 || height == 0 {
+        return 0.0;
+    }
+    let histogram = luminance_histogram(data, width, height, metering);
+    let total: f64 = histogram.iter().sum();
+    if total <= 0.0 {
+        return 0.0;
+    }
+    let linear = |bin: usize| decode((bin as f32 + 0.5) / BINS as f32, color_space);
+    let mid = linear(percentile(&histogram, total, MID_PERCENTILE));
+    let high = linear(percentile(&histogram, total, HIGHLIGHT_PERCENTILE));
+
+    let mut ev = (MID_TARGET / mid).log2();
+    if ev > 0.0 {
+        ev = ev.min((HIGHLIGHT_TARGET / high).log2().max(0.0));
+    }
+    ev.clamp(EV_RANGE.0, EV_RANGE.1)
+}
+
+/// 按测光模式加权的亮度直方图
+fn luminance_histogram(data: &[u16], width: usize, height: usize, metering: Metering) -> Vec<f64> {
+    let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+    // 中央重点测光的权重在短边一半处衰减到约 0.6
+    let sigma2 = {
+        let s = width.min(height).max(1) as f32 / 2.0;
+        2.0 * s * s
+    };
+    data.par_chunks_exact(width * 3)
+        .enumerate()
+        .fold(
+            || vec![0.0f64; BINS],
+            |mut hist, (y, row)| {
+                let dy = y as f32 + 0.5 - cy;
+                for (x, p) in row.chunks_exact(3).enumerate() {
+                    let luma = 0.2126 * p[0] as f32 + 0.7152 * p[1] as f32 + 0.0722 * p[2] as f32;
+                    let bin = ((luma / 65535.0 * BINS as f32) as usize).min(BINS - 1);
+                    let weight = match metering {
+                        Metering::Average => 1.0,
+                        Metering::CenterWeighted => {
+                            let dx = x as f32 + 0.5 - cx;
+                            (-(dx * dx + dy * dy) / sigma2).exp()
+                        }
+                    };
+                    hist[bin] += weight as f64;
+                }
+                hist
+            },
+        )
+        .reduce(
+            || vec![0.0f64; BINS],
+            |mut a, b| {
+                a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+                a
+            },
+        )
+}
+
+fn percentile(histogram: &[f64], total: f64, p: f32) -> usize {
+    let target = total * p as f64;
+    let mut sum = 0.0;
+    for (bin, &count) in histogram.iter().enumerate() {
+        sum += count;
+        if sum >= target {
+            return bin;
+        }
+    }
+    histogram.len() - 1
+}
@@ -0,0 +1,31 @@
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+
+use crate::Rgb16Image;
+
+/// 任一通道不低于该值视为高光溢出，对应 8 位输出的 254
+const HIGHLIGHT_THRESHOLD: u16 = 254 * 257;
+/// 所有通道不高于该值视为暗部溢出，对应 8 位输出的 1
+const SHADOW_THRESHOLD: u16 = 257;
+
+const HIGHLIGHT_COLOR: Rgba<u8> = Rgba([255, 0, 0, 255]);
+const SHADOW_COLOR: Rgba<u8> = Rgba([0, 96, 255, 255]);
+
+/// 生成与图像同尺寸的溢出警告蒙版，高光溢出标红，暗部溢出标蓝，其余透明
+pub fn clipping_mask(image: &Rgb16Image) -> RgbaImage {
+    let data: Vec<u8> = image
+        .as_raw()
+        .par_chunks_exact(3)
+        .flat_map_iter(|p| {
+            let color = if p.iter().any(|&v| v >= HIGHLIGHT_THRESHOLD) {
+                HIGHLIGHT_COLOR
+            } else if p.iter().all(|&v| v <= SHADOW_THRESHOLD) {
+                SHADOW_COLOR
+            } else {
+                Rgba([0; 4])
+            };
+            color.0
+        })
+        .collect();
+    RgbaImage::from_raw(image.width(), image.height(), data).unwrap()
+}
@@ -0,0 +1,166 @@
+use std::ffi::{c_void, CString};
+use std::io;
+use std::ptr;
+
+use libraw_rs_vendor::{
+    libraw_close, libraw_colordata_t, libraw_data_t, libraw_dcraw_clear_mem, libraw_dcraw_make_mem_image,
+    libraw_dcraw_process, libraw_image_sizes_t, libraw_imgother_t, libraw_init, libraw_iparams_t,
+    libraw_lensinfo_t, libraw_open_buffer, libraw_open_file, libraw_output_params_t,
+    libraw_processed_image_t, libraw_unpack, LibRaw_errors_LIBRAW_SUCCESS,
+};
+
+use crate::error::RawError;
+
+fn check(code: i32) -> Result<(), RawError> {
+    if code == LibRaw_errors_LIBRAW_SUCCESS {
+        Ok(())
+    } else {
+        Err(RawError::from_code(code))
+    }
+}
+
+/// libraw 句柄，析构时自动释放
+pub struct RawDecoder {
+    data: *mut libraw_data_t,
+    // libraw_open_buffer 不复制数据，需要在句柄存活期间保留
+    _buffer: Vec<u8>,
+}
+
+impl RawDecoder {
+    /// 打开并解包 Raw 文件
+    pub fn open(path: &str) -> Result<Self, RawError> {
+        let path_c = CString::new(path)
+            .map_err(|e| RawError::Io(io::Error::new(io::ErrorKind::InvalidInput, e)))?;
+        let data = unsafe { libraw_init(0) };
+        if data.is_null() {
+            return Err(RawError::OutOfMemory);
+        }
+        let decoder = RawDecoder {
+            data,
+            _buffer: Vec::new(),
+        };
+        unsafe {
+            check(libraw_open_file(decoder.data, path_c.as_ptr()))?;
+            check(libraw_unpack(decoder.data))?;
+        }
+        Ok(decoder)
+    }
+
+    /// 从内存中打开并解包 Raw 数据
+    pub fn from_buffer(buffer: &[u8]) -> Result<Self, RawError> {
+        let data = unsafe { libraw_init(0) };
+        if data.is_null() {
+            return Err(RawError::OutOfMemory);
+        }
+        let decoder = RawDecoder {
+            data,
+            _buffer: buffer.to_vec(),
+        };
+        unsafe {
+            check(libraw_open_buffer(
+                decoder.data,
+                decoder._buffer.as_ptr() as *const c_void,
+                decoder._buffer.len(),
+            ))?;
+            check(libraw_unpack(decoder.data))?;
+        }
+        Ok(decoder)
+    }
+
+    pub(crate) fn params(&mut self) -> &mut libraw_output_params_t {
+        unsafe { &mut (*self.data).params }
+    }
+
+    pub(crate) fn other(&self) -> &libraw_imgother_t {
+        unsafe { &(*self.data).other }
+    }
+
+    pub(crate) fn lens(&self) -> &libraw_lensinfo_t {
+        unsafe { &(*self.data).lens }
+    }
+
+    pub(crate) fn idata(&self) -> &libraw_iparams_t {
+        unsafe { &(*self.data).idata }
+    }
+
+    pub(crate) fn color(&self) -> &libraw_colordata_t {
+        unsafe { &(*self.data).color }
+    }
+
+    pub(crate) fn sizes(&self) -> &libraw_image_sizes_t {
+        unsafe { &(*self.data).sizes }
+    }
+
+    /// 按当前参数执行 dcraw 处理并输出内存图像
+    pub fn process(&mut self) -> Result<ProcessedImage, RawError> {
+        unsafe {
+            check(libraw_dcraw_process(self.data))?;
+            let mut errc = 0;
+            let img = libraw_dcraw_make_mem_image(self.data, &mut errc);
+            if img.is_null() {
+                return Err(if errc == 0 {
+                    RawError::OutOfMemory
+                } else {
+                    RawError::from_code(errc)
+                });
+            }
+            Ok(ProcessedImage { img })
+        }
+    }
+}
+
+impl Drop for RawDecoder {
+    fn drop(&mut self) {
+        unsafe { libraw_close(self.data) };
+        self.data = ptr::null_mut();
+    }
+}
+
+/// libraw 输出的内存图像，析构时自动释放
+pub struct ProcessedImage {
+    img: *mut libraw_processed_image_t,
+}
+
+impl ProcessedImage {
+    pub fn width(&self) -> u32 {
+        unsafe { (*self.img).width as u32 }
+    }
+
+    pub fn height(&self) -> u32 {
+        unsafe { (*self.img).height as u32 }
+    }
+
+    pub fn colors(&self) -> u32 {
+        unsafe { (*self.img).colors as u32 }
+    }
+
+    pub fn bits(&self) -> u32 {
+        unsafe { (*self.img).bits as u32 }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        unsafe {
+            let raw_data = (*self.img).data.as_ptr();
+            let raw_size = (*self.img).data_size as usize;
+            std::slice::from_raw_parts(raw_data, raw_size)
+        }
+    }
+
+    /// 以 16 位通道返回图像数据，8 位输出会扩展到 16 位
+    pub fn data_u16(&self) -> Vec<u16> {
+        if self.bits() == 16 {
+            self.data()
+                .chunks_exact(2)
+                .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+                .collect()
+        } else {
+            self.data().iter().map(|&v| v as u16 * 257).collect()
+        }
+    }
+}
+
+impl Drop for ProcessedImage {
+    fn drop(&mut self) {
+        unsafe { libraw_dcraw_clear_mem(self.img) };
+    }
+}
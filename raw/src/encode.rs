@@ -0,0 +1,259 @@
+use std::io::Cursor;
+
+use exif::experimental::Writer;
+use exif::{Context, Field, In, Rational, Tag, Value};
+use image::codecs::avif::AvifEncoder;
+use image::codecs::png::PngEncoder;
+use image::{ExtendedColorType, ImageEncoder, RgbImage};
+use img_parts::riff::RiffContent;
+use img_parts::{jpeg::Jpeg, png::Png, webp::WebP, Bytes, ImageEXIF, ImageICC};
+use jpegxl_rs::encode::{EncoderResult, Metadata};
+use jpegxl_rs::encoder_builder;
+use rayon::prelude::*;
+use webp::Encoder;
+
+use crate::error::RawError;
+use crate::icc::icc_profile;
+use crate::options::{BitDepth, ColorSpace, OutputFormat};
+use crate::{Myexif, Rgb16Image};
+
+fn encode_err(e: impl std::fmt::Display) -> RawError {
+    RawError::Encode(e.to_string())
+}
+
+/// 将 16 位图像量化为 8 位
+fn quantize(image: &Rgb16Image) -> RgbImage {
+    let data = image
+        .as_raw()
+        .par_iter()
+        .map(|&v| ((v as u32 + 128) / 257) as u8)
+        .collect();
+    RgbImage::from_raw(image.width(), image.height(), data).unwrap()
+}
+
+/// AVIF 编码速度，范围 1-10，越小压缩率越高
+const AVIF_SPEED: u8 = 6;
+
+/// 将图像编码为指定格式，exif 不为空时一并写入
+///
+/// JPEG、WebP 和 AVIF 忽略位深始终输出 8 位，JPEG XL 始终使用 16 位输入，
+/// JPEG、WebP、PNG 和 TIFF 会嵌入色彩空间对应的配置文件
+pub fn encode(
+    image: &Rgb16Image,
+    format: OutputFormat,
+    quality: u8,
+    bit_depth: BitDepth,
+    color_space: ColorSpace,
+    exif: Option<&Myexif>,
+) -> Result<Vec<u8>, RawError> {
+    let icc = Bytes::from(icc_profile(color_space));
+    match format {
+        OutputFormat::Png => encode_png(image, bit_depth, icc, exif),
+        OutputFormat::Tiff => encode_tiff(image, bit_depth, icc, exif),
+        OutputFormat::Jxl => encode_jxl(image, quality, exif),
+        OutputFormat::Avif => {
+            let image = &quantize(image);
+            let mut buf = Vec::new();
+            let mut encoder = AvifEncoder::new_with_speed_quality(&mut buf, AVIF_SPEED, quality);
+            if let Some(exif) = exif {
+                encoder.set_exif_metadata(exif_bytes(exif)?).map_err(encode_err)?;
+            }
+            encoder
+                .write_image(image.as_raw(), image.width(), image.height(), ExtendedColorType::Rgb8)
+                .map_err(encode_err)?;
+            Ok(buf)
+        }
+        OutputFormat::Webp => {
+            let image = &quantize(image);
+            let encoder = Encoder::from_rgb(image.as_raw(), image.width(), image.height());
+            let webp = encoder.encode(quality as f32).to_vec();
+            let mut webp = WebP::from_bytes(webp.into()).map_err(encode_err)?;
+            if let Some(exif) = exif {
+                let exif = Bytes::from(exif_bytes(exif)?);
+                webp.set_exif(Some(exif.clone()));
+                // img_parts 会加上 JPEG 的 "Exif\0\0" 前缀，WebP 规范要求直接存放 TIFF 数据
+                for chunk in webp.chunks_mut().iter_mut().filter(|c| c.id() == *b"EXIF") {
+                    *chunk.content_mut() = RiffContent::Data(exif.clone());
+                }
+            }
+            // img_parts 不会更新已有 VP8X 块的标志位，移除后由最后一次写入按全部元数据重建
+            webp.chunks_mut().retain(|chunk| chunk.id() != *b"VP8X");
+            webp.set_icc_profile(Some(icc));
+            Ok(webp.encoder().bytes().to_vec())
+        }
+        OutputFormat::Jpeg => {
+            let image = &quantize(image);
+            let width = u16::try_from(image.width()).map_err(encode_err)?;
+            let height = u16::try_from(image.height()).map_err(encode_err)?;
+            let mut buf = Vec::new();
+            let mut encoder = jpeg_encoder::Encoder::new(&mut buf, quality);
+            encoder.set_progressive(true);
+            encoder
+                .encode(image.as_raw(), width, height, jpeg_encoder::ColorType::Rgb)
+                .map_err(encode_err)?;
+            let mut jpeg = Jpeg::from_bytes(buf.into()).map_err(encode_err)?;
+            jpeg.set_icc_profile(Some(icc));
+            if let Some(exif) = exif {
+                jpeg.set_exif(Some(Bytes::from(exif_bytes(exif)?)));
+            }
+            // 编码器在 APP0 之后直接写入帧头，部分读取器要求 APP 段位于帧头之前
+            jpeg.segments_mut()
+                .sort_by_key(|segment| !(0xE0..=0xEF).contains(&segment.marker()));
+            Ok(jpeg.encoder().bytes().to_vec())
+        }
+    }
+}
+
+fn encode_png(
+    image: &Rgb16Image,
+    bit_depth: BitDepth,
+    icc: Bytes,
+    exif: Option<&Myexif>,
+) -> Result<Vec<u8>, RawError> {
+    let mut buf = Vec::new();
+    let encoder = PngEncoder::new(&mut buf);
+    match bit_depth {
+        BitDepth::Eight => {
+            let image = quantize(image);
+            encoder.write_image(image.as_raw(), image.width(), image.height(), ExtendedColorType::Rgb8)
+        }
+        BitDepth::Sixteen => {
+            // PNG 使用大端序存储 16 位数据，这里按本机字节序传入，由编码器转换
+            let bytes: Vec<u8> = image.as_raw().iter().flat_map(|v| v.to_ne_bytes()).collect();
+            encoder.write_image(&bytes, image.width(), image.height(), ExtendedColorType::Rgb16)
+        }
+    }
+    .map_err(encode_err)?;
+
+    let mut png = Png::from_bytes(buf.into()).map_err(encode_err)?;
+    png.set_icc_profile(Some(icc));
+    if let Some(exif) = exif {
+        png.set_exif(Some(Bytes::from(exif_bytes(exif)?)));
+    }
+    Ok(png.encoder().bytes().to_vec())
+}
+
+/// 使用 exif 写入器直接生成 TIFF，exif 字段写入 Exif IFD，配置文件写入主 IFD
+fn encode_tiff(
+    image: &Rgb16Image,
+    bit_depth: BitDepth,
+    icc: Bytes,
+    exif: Option<&Myexif>,
+) -> Result<Vec<u8>, RawError> {
+    let (bits, data): (u16, Vec<u8>) = match bit_depth {
+        BitDepth::Eight => (8, quantize(image).into_raw()),
+        BitDepth::Sixteen => (16, image.as_raw().iter().flat_map(|v| v.to_le_bytes()).collect()),
+    };
+    let field = |tag, value| Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value,
+    };
+    let mut fields = vec![
+        field(Tag::ImageWidth, Value::Long(vec![image.width()])),
+        field(Tag::ImageLength, Value::Long(vec![image.height()])),
+        field(Tag::BitsPerSample, Value::Short(vec![bits; 3])),
+        field(Tag::Compression, Value::Short(vec![1])),
+        field(Tag::PhotometricInterpretation, Value::Short(vec![2])),
+        field(Tag::SamplesPerPixel, Value::Short(vec![3])),
+        field(Tag::RowsPerStrip, Value::Long(vec![image.height()])),
+        field(Tag::PlanarConfiguration, Value::Short(vec![1])),
+        field(TAG_ICC_PROFILE, Value::Undefined(icc.to_vec(), 0)),
+    ];
+    if let Some(exif) = exif {
+        fields.extend(exif_fields(exif));
+    }
+    let strips = [data.as_slice()];
+
+    let mut writer = Writer::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    writer.set_strips(&strips, In::PRIMARY);
+    let mut buf = Cursor::new(Vec::new());
+    writer.write(&mut buf, true).map_err(encode_err)?;
+    Ok(buf.into_inner())
+}
+
+/// 按 libjxl 的规则将 1-100 的质量换算为 butteraugli 距离
+fn jxl_distance(quality: u8) -> f32 {
+    let quality = quality as f32;
+    if quality >= 30.0 {
+        0.1 + (100.0 - quality) * 0.09
+    } else {
+        6.24 + f32::powf(2.5, (30.0 - quality) / 5.0) / 6.25
+    }
+}
+
+fn encode_jxl(image: &Rgb16Image, quality: u8, exif: Option<&Myexif>) -> Result<Vec<u8>, RawError> {
+    let mut encoder = encoder_builder()
+        .quality(jxl_distance(quality))
+        .use_container(exif.is_some())
+        .build()
+        .map_err(encode_err)?;
+    if let Some(exif) = exif {
+        // Exif 盒子以 4 字节的 TIFF 头偏移开始
+        let mut data = vec![0; 4];
+        data.extend(exif_bytes(exif)?);
+        encoder
+            .add_metadata(&Metadata::Exif(&data), true)
+            .map_err(encode_err)?;
+    }
+    let result: EncoderResult<u16> = encoder
+        .encode(image.as_raw(), image.width(), image.height())
+        .map_err(encode_err)?;
+    Ok(result.data)
+}
+
+/// TIFF 的 InterColorProfile 标签
+const TAG_ICC_PROFILE: Tag = Tag(Context::Tiff, 34675);
+
+/// 原文件的 exif 字段，缺失的基本拍摄参数用 libraw 解析的值补全
+fn exif_fields(exif: &Myexif) -> Vec<Field> {
+    let exposure_time = Field {
+        tag: Tag::ExposureTime,
+        ifd_num: In::PRIMARY,
+        value: Value::Float(vec![exif.shutter]),
+    };
+    let f_number = Field {
+        tag: Tag::FNumber,
+        ifd_num: In::PRIMARY,
+        value: Value::Float(vec![exif.aperture]),
+    };
+    let focal_length = Field {
+        tag: Tag::FocalLength,
+        ifd_num: In::PRIMARY,
+        value: Value::Rational(vec![Rational {
+            num: (exif.focal_length * 10.0).round() as u32,
+            denom: 10,
+        }]),
+    };
+    let focal_len_35mm = Field {
+        tag: Tag::FocalLengthIn35mmFilm,
+        ifd_num: In::PRIMARY,
+        value: Value::Short(vec![exif.focal_len]),
+    };
+    let iso = Field {
+        tag: Tag::PhotographicSensitivity,
+        ifd_num: In::PRIMARY,
+        value: Value::Long(vec![exif.iso as u32]),
+    };
+    let mut fields = exif.source.fields().to_vec();
+    for field in [exposure_time, f_number, focal_length, focal_len_35mm, iso] {
+        if !fields.iter().any(|f| f.tag == field.tag) {
+            fields.push(field);
+        }
+    }
+    fields
+}
+
+fn exif_bytes(exif: &Myexif) -> Result<Vec<u8>, RawError> {
+    let fields = exif_fields(exif);
+    let mut writer = Writer::new();
+    let mut buf = Cursor::new(Vec::new());
+    for field in &fields {
+        writer.push_field(field);
+    }
+    writer.write(&mut buf, false).map_err(encode_err)?;
+    Ok(buf.into_inner())
+}
@@ -0,0 +1,142 @@
+use std::{fmt, io};
+
+use crate::options::OutputFormat;
+
+use libraw_rs_vendor::{
+    LibRaw_errors_LIBRAW_DATA_ERROR, LibRaw_errors_LIBRAW_FILE_UNSUPPORTED,
+    LibRaw_errors_LIBRAW_IO_ERROR, LibRaw_errors_LIBRAW_UNSUFFICIENT_MEMORY,
+};
+
+/// Raw 处理过程中的错误
+#[derive(Debug)]
+pub enum RawError {
+    /// 不支持的文件格式
+    Unsupported,
+    /// 文件数据损坏
+    CorruptData,
+    /// 内存不足
+    OutOfMemory,
+    /// 文件读写错误
+    Io(io::Error),
+    /// 其它 libraw 错误码
+    LibRaw(i32),
+    /// 图像编码错误
+    Encode(String),
+    /// 无法识别的输出格式
+    UnknownFormat(String),
+    /// 输出文件扩展名与指定的输出格式不一致
+    FormatMismatch { path: String, format: OutputFormat },
+    /// LUT 文件错误
+    Lut(LutError),
+}
+
+impl RawError {
+    /// 将 libraw 返回码转换为错误，正数为系统 errno，负数为 libraw 错误码
+    pub(crate) fn from_code(code: i32) -> Self {
+        #[allow(non_upper_case_globals)]
+        match code {
+            c if c > 0 => RawError::Io(io::Error::from_raw_os_error(c)),
+            LibRaw_errors_LIBRAW_FILE_UNSUPPORTED => RawError::Unsupported,
+            LibRaw_errors_LIBRAW_DATA_ERROR => RawError::CorruptData,
+            LibRaw_errors_LIBRAW_UNSUFFICIENT_MEMORY => RawError::OutOfMemory,
+            LibRaw_errors_LIBRAW_IO_ERROR => RawError::Io(io::Error::other("libraw I/O error")),
+            c => RawError::LibRaw(c),
+        }
+    }
+}
+
+impl fmt::Display for RawError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RawError::Unsupported => write!(f, "unsupported raw file"),
+            RawError::CorruptData => write!(f, "corrupt raw data"),
+            RawError::OutOfMemory => write!(f, "out of memory"),
+            RawError::Io(e) => write!(f, "I/O error: {}", e),
+            RawError::LibRaw(code) => write!(f, "libraw error {}", code),
+            RawError::Encode(msg) => write!(f, "encode error: {}", msg),
+            RawError::UnknownFormat(ext) => write!(f, "unknown output format: {}", ext),
+            RawError::FormatMismatch { path, format } => {
+                write!(f, "output file {} does not match format {}", path, format.extension())
+            }
+            RawError::Lut(e) => write!(f, "LUT error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RawError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RawError::Io(e) => Some(e),
+            RawError::Lut(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RawError {
+    fn from(e: io::Error) -> Self {
+        RawError::Io(e)
+    }
+}
+
+impl From<LutError> for RawError {
+    fn from(e: LutError) -> Self {
+        RawError::Lut(e)
+    }
+}
+
+/// LUT 文件解析错误
+#[derive(Debug)]
+pub enum LutError {
+    /// 文件读写错误
+    Io(io::Error),
+    /// 无法识别的 LUT 格式
+    UnknownFormat,
+    /// 文件内容不合法，line 为出错的行号，从 1 开始
+    Invalid { line: Option<usize>, message: String },
+}
+
+impl LutError {
+    pub(crate) fn invalid(message: impl Into<String>) -> Self {
+        LutError::Invalid {
+            line: None,
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn at(line: usize, message: impl Into<String>) -> Self {
+        LutError::Invalid {
+            line: Some(line),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for LutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LutError::Io(e) => write!(f, "I/O error: {}", e),
+            LutError::UnknownFormat => write!(f, "unknown LUT format"),
+            LutError::Invalid {
+                line: Some(line),
+                message,
+            } => write!(f, "line {}: {}", line, message),
+            LutError::Invalid { line: None, message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for LutError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LutError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LutError {
+    fn from(e: io::Error) -> Self {
+        LutError::Io(e)
+    }
+}
@@ -0,0 +1,144 @@
+use rayon::prelude::*;
+
+use crate::options::{AspectRatio, CropRect, Geometry};
+
+/// 几何变换的计算结果，输出图像上的坐标可以直接映射回原图，只需一次重采样
+///
+/// 坐标均为连续坐标，像素 i 的中心为 i + 0.5
+pub(crate) struct Transform {
+    width: f32,
+    height: f32,
+    rotate: u8,
+    sin: f32,
+    cos: f32,
+    /// 90° 旋转后的尺寸
+    rotated: (f32, f32),
+    /// 拉直后的尺寸
+    straightened: (f32, f32),
+    /// 裁切区域左上角在拉直后图像中的位置
+    offset: (f32, f32),
+    pub(crate) out_width: usize,
+    pub(crate) out_height: usize,
+}
+
+impl Transform {
+    pub(crate) fn new(geometry: &Geometry, width: usize, height: usize) -> Self {
+        let (w, h) = (width as f32, height as f32);
+        let rotate = geometry.rotate % 4;
+        let rotated = if rotate % 2 == 1 { (h, w) } else { (w, h) };
+        let angle = geometry.straighten.clamp(-45.0, 45.0).to_radians();
+        let (sin, cos) = angle.sin_cos();
+        let straightened = if geometry.auto_crop && angle != 0.0 {
+            // 与原图比例相同、完全落在旋转后图像内的最大矩形
+            let (rw, rh) = rotated;
+            let s = (rw / (rw * cos + rh * sin.abs())).min(rh / (rw * sin.abs() + rh * cos));
+            (rw * s, rh * s)
+        } else {
+            rotated
+        };
+        let (sw, sh) = straightened;
+        let crop = geometry.crop.unwrap_or(CropRect {
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+        });
+        let x = crop.x.clamp(0.0, 1.0);
+        let y = crop.y.clamp(0.0, 1.0);
+        let (mut cx, mut cy) = (x * sw, y * sh);
+        let mut cw = crop.width.clamp(0.0, 1.0 - x) * sw;
+        let mut ch = crop.height.clamp(0.0, 1.0 - y) * sh;
+        if let Some(mut ratio) = geometry.aspect.ratio(sw / sh) {
+            // 比例预设跟随裁切区域的横竖方向
+            if ch > cw && ratio > 1.0 && geometry.aspect != AspectRatio::Original {
+                ratio = 1.0 / ratio;
+            }
+            if cw / ch > ratio {
+                cx += (cw - ch * ratio) / 2.0;
+                cw = ch * ratio;
+            } else {
+                cy += (ch - cw / ratio) / 2.0;
+                ch = cw / ratio;
+            }
+        }
+        Transform {
+            width: w,
+            height: h,
+            rotate,
+            sin,
+            cos,
+            rotated,
+            straightened,
+            offset: (cx.round(), cy.round()),
+            out_width: (cw.round() as usize).max(1),
+            out_height: (ch.round() as usize).max(1),
+        }
+    }
+
+    /// 输出图像上的点在原图中的位置
+    pub(crate) fn source(&self, x: f32, y: f32) -> (f32, f32) {
+        let (x, y) = (x + self.offset.0, y + self.offset.1);
+        // 拉直为顺时针旋转，反向映射时逆时针旋转
+        let dx = x - self.straightened.0 / 2.0;
+        let dy = y - self.straightened.1 / 2.0;
+        let x = dx * self.cos + dy * self.sin + self.rotated.0 / 2.0;
+        let y = -dx * self.sin + dy * self.cos + self.rotated.1 / 2.0;
+        match self.rotate {
+            1 => (y, self.height - x),
+            2 => (self.width - x, self.height - y),
+            3 => (self.width - y, x),
+            _ => (x, y),
+        }
+    }
+
+    /// 将输出图像上的相对坐标映射为原图上的相对坐标
+    pub(crate) fn source_relative(&self, x: f32, y: f32) -> (f32, f32) {
+        let (sx, sy) = self.source(x * self.out_width as f32, y * self.out_height as f32);
+        (sx / self.width, sy / self.height)
+    }
+}
+
+/// 按 90° 旋转、拉直、裁切的顺序变换图像，返回新的数据和尺寸
+///
+/// 不自动裁切时，拉直后超出原图的区域填充黑色
+pub(crate) fn apply_geometry(
+    data: Vec<u16>,
+    width: usize,
+    height: usize,
+    geometry: &Geometry,
+) -> (Vec<u16>, usize, usize) {
+    if geometry.is_identity() || width == 0 || height == 0 {
+        return (data, width, height);
+    }
+    let transform = Transform::new(geometry, width, height);
+    let (out_width, out_height) = (transform.out_width, transform.out_height);
+    let mut out = vec![0u16; out_width * out_height * 3];
+    out.par_chunks_exact_mut(out_width * 3).enumerate().for_each(|(y, row)| {
+        for (x, p) in row.chunks_exact_mut(3).enumerate() {
+            let (sx, sy) = transform.source(x as f32 + 0.5, y as f32 + 0.5);
+            if sx < 0.0 || sy < 0.0 || sx > width as f32 || sy > height as f32 {
+                continue;
+            }
+            p.copy_from_slice(&sample(&data, width, height, sx - 0.5, sy - 0.5));
+        }
+    });
+    (out, out_width, out_height)
+}
+
+/// RGB 数据单个通道的双线性取样，坐标超出范围时使用边缘像素
+pub(crate) fn sample_channel(data: &[u16], width: usize, height: usize, x: f32, y: f32, ch: usize) -> u16 {
+    let x = x.clamp(0.0, (width - 1) as f32);
+    let y = y.clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let at = |x: usize, y: usize| data[(y * width + x) * 3 + ch] as f32;
+    let top = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+    let bottom = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+    (top * (1.0 - fy) + bottom * fy).round() as u16
+}
+
+/// RGB 三个通道的双线性取样
+pub(crate) fn sample(data: &[u16], width: usize, height: usize, x: f32, y: f32) -> [u16; 3] {
+    std::array::from_fn(|ch| sample_channel(data, width, height, x, y, ch))
+}
@@ -0,0 +1,241 @@
+use crate::options::ColorSpace;
+
+/// 色调响应曲线
+#[derive(Debug, Clone, Copy)]
+enum Trc {
+    /// sRGB 分段曲线
+    Srgb,
+    /// 纯幂函数曲线，1.0 为线性
+    Gamma(f64),
+}
+
+impl Trc {
+    fn eval(&self, v: f64) -> f64 {
+        match *self {
+            Trc::Srgb => {
+                if v <= 0.04045 {
+                    v / 12.92
+                } else {
+                    ((v + 0.055) / 1.055).powf(2.4)
+                }
+            }
+            Trc::Gamma(gamma) => v.powf(gamma),
+        }
+    }
+}
+
+/// 显示器 RGB 色彩空间描述，原色和白点为 CIE xy 坐标
+struct RgbSpace {
+    desc: &'static str,
+    primaries: [[f64; 2]; 3],
+    white: [f64; 2],
+    trc: Trc,
+}
+
+const SRGB: RgbSpace = RgbSpace {
+    desc: "sRGB IEC61966-2.1",
+    primaries: [[0.64, 0.33], [0.30, 0.60], [0.15, 0.06]],
+    white: [0.3127, 0.3290],
+    trc: Trc::Srgb,
+};
+
+const ADOBE_RGB: RgbSpace = RgbSpace {
+    desc: "Adobe RGB (1998)",
+    primaries: [[0.64, 0.33], [0.21, 0.71], [0.15, 0.06]],
+    white: [0.3127, 0.3290],
+    trc: Trc::Gamma(563.0 / 256.0),
+};
+
+const DISPLAY_P3: RgbSpace = RgbSpace {
+    desc: "Display P3",
+    primaries: [[0.680, 0.320], [0.265, 0.690], [0.150, 0.060]],
+    white: [0.3127, 0.3290],
+    trc: Trc::Srgb,
+};
+
+const PROPHOTO_RGB: RgbSpace = RgbSpace {
+    desc: "ProPhoto RGB",
+    primaries: [[0.7347, 0.2653], [0.1596, 0.8404], [0.0366, 0.0001]],
+    white: [0.3457, 0.3585],
+    trc: Trc::Gamma(1.8),
+};
+
+const REC2020_LINEAR: RgbSpace = RgbSpace {
+    desc: "Rec. 2020 Linear",
+    primaries: [[0.708, 0.292], [0.170, 0.797], [0.131, 0.046]],
+    white: [0.3127, 0.3290],
+    trc: Trc::Gamma(1.0),
+};
+
+/// ICC 规定的 PCS 白点 D50
+const D50: [f64; 3] = [0.9642, 1.0, 0.8249];
+
+/// 输出色彩空间对应的 ICC 配置文件
+pub fn icc_profile(color_space: ColorSpace) -> Vec<u8> {
+    build_profile(match color_space {
+        ColorSpace::Srgb => &SRGB,
+        ColorSpace::AdobeRgb => &ADOBE_RGB,
+        ColorSpace::DisplayP3 => &DISPLAY_P3,
+        ColorSpace::ProPhoto => &PROPHOTO_RGB,
+        ColorSpace::Rec2020Linear => &REC2020_LINEAR,
+    })
+}
+
+fn xy_to_xyz([x, y]: [f64; 2]) -> [f64; 3] {
+    [x / y, 1.0, (1.0 - x - y) / y]
+}
+
+fn mat_mul(a: &[[f64; 3]; 3], b: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    [0, 1, 2].map(|i| [0, 1, 2].map(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+fn mat_vec(m: &[[f64; 3]; 3], v: &[f64; 3]) -> [f64; 3] {
+    [0, 1, 2].map(|i| m[i][0] * v[0] + m[i][1] * v[1] + m[i][2] * v[2])
+}
+
+fn mat_inv(m: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+    [0, 1, 2].map(|i| {
+        [0, 1, 2].map(|j| {
+            let (r0, r1) = ((j + 1) % 3, (j + 2) % 3);
+            let (c0, c1) = ((i + 1) % 3, (i + 2) % 3);
+            (m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]) / det
+        })
+    })
+}
+
+/// 计算 RGB 到 XYZ 的矩阵，并用 Bradford 变换适配到 D50
+fn colorants(space: &RgbSpace) -> [[f64; 3]; 3] {
+    let p = space.primaries.map(xy_to_xyz);
+    let m = [
+        [p[0][0], p[1][0], p[2][0]],
+        [p[0][1], p[1][1], p[2][1]],
+        [p[0][2], p[1][2], p[2][2]],
+    ];
+    let white = xy_to_xyz(space.white);
+    let s = mat_vec(&mat_inv(&m), &white);
+    let rgb_to_xyz = [0, 1, 2].map(|i| [0, 1, 2].map(|j| m[i][j] * s[j]));
+
+    const BRADFORD: [[f64; 3]; 3] = [
+        [0.8951, 0.2664, -0.1614],
+        [-0.7502, 1.7135, 0.0367],
+        [0.0389, -0.0685, 1.0296],
+    ];
+    let src = mat_vec(&BRADFORD, &white);
+    let dst = mat_vec(&BRADFORD, &D50);
+    let scale = [
+        [dst[0] / src[0], 0.0, 0.0],
+        [0.0, dst[1] / src[1], 0.0],
+        [0.0, 0.0, dst[2] / src[2]],
+    ];
+    let adapt = mat_mul(&mat_inv(&BRADFORD), &mat_mul(&scale, &BRADFORD));
+    mat_mul(&adapt, &rgb_to_xyz)
+}
+
+fn s15fixed16(v: f64) -> [u8; 4] {
+    ((v * 65536.0).round() as i32).to_be_bytes()
+}
+
+fn xyz_tag(xyz: [f64; 3]) -> Vec<u8> {
+    let mut tag = b"XYZ \0\0\0\0".to_vec();
+    for v in xyz {
+        tag.extend_from_slice(&s15fixed16(v));
+    }
+    tag
+}
+
+fn desc_tag(desc: &str) -> Vec<u8> {
+    let mut tag = b"desc\0\0\0\0".to_vec();
+    tag.extend_from_slice(&(desc.len() as u32 + 1).to_be_bytes());
+    tag.extend_from_slice(desc.as_bytes());
+    tag.push(0);
+    // 空的 Unicode 和 ScriptCode 描述
+    tag.extend_from_slice(&[0; 4 + 4 + 2 + 1 + 67]);
+    tag
+}
+
+fn text_tag(text: &str) -> Vec<u8> {
+    let mut tag = b"text\0\0\0\0".to_vec();
+    tag.extend_from_slice(text.as_bytes());
+    tag.push(0);
+    tag
+}
+
+fn curv_tag(trc: Trc) -> Vec<u8> {
+    let mut tag = b"curv\0\0\0\0".to_vec();
+    if let Trc::Gamma(gamma) = trc {
+        // 幂函数曲线只需一个 u8Fixed8 格式的指数，线性曲线不需要数据
+        if gamma == 1.0 {
+            tag.extend_from_slice(&0u32.to_be_bytes());
+        } else {
+            tag.extend_from_slice(&1u32.to_be_bytes());
+            tag.extend_from_slice(&((gamma * 256.0).round() as u16).to_be_bytes());
+        }
+        return tag;
+    }
+    const POINTS: u32 = 1024;
+    tag.extend_from_slice(&POINTS.to_be_bytes());
+    for i in 0..POINTS {
+        let v = trc.eval(i as f64 / (POINTS - 1) as f64);
+        tag.extend_from_slice(&((v * 65535.0).round() as u16).to_be_bytes());
+    }
+    tag
+}
+
+/// 生成 ICC v2 显示器配置文件
+fn build_profile(space: &RgbSpace) -> Vec<u8> {
+    let m = colorants(space);
+    let curv = curv_tag(space.trc);
+    // 三个通道共用同一条曲线数据
+    let tags: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (b"desc", desc_tag(space.desc)),
+        (b"cprt", text_tag("No copyright, use freely")),
+        (b"wtpt", xyz_tag(xy_to_xyz(space.white))),
+        (b"rXYZ", xyz_tag([m[0][0], m[1][0], m[2][0]])),
+        (b"gXYZ", xyz_tag([m[0][1], m[1][1], m[2][1]])),
+        (b"bXYZ", xyz_tag([m[0][2], m[1][2], m[2][2]])),
+        (b"rTRC", curv.clone()),
+    ];
+
+    let table_len = 4 + (tags.len() + 2) * 12;
+    let mut offset = 128 + table_len;
+    let mut table = Vec::with_capacity(table_len);
+    let mut body = Vec::new();
+    table.extend_from_slice(&(tags.len() as u32 + 2).to_be_bytes());
+    for (sig, data) in &tags {
+        table.extend_from_slice(*sig);
+        table.extend_from_slice(&(offset as u32).to_be_bytes());
+        table.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        body.extend_from_slice(data);
+        while body.len() % 4 != 0 {
+            body.push(0);
+        }
+        offset = 128 + table_len + body.len();
+    }
+    let trc_offset = 128 + table_len + body.len() - curv.len().next_multiple_of(4);
+    for sig in [b"gTRC", b"bTRC"] {
+        table.extend_from_slice(sig);
+        table.extend_from_slice(&(trc_offset as u32).to_be_bytes());
+        table.extend_from_slice(&(curv.len() as u32).to_be_bytes());
+    }
+
+    let size = 128 + table.len() + body.len();
+    let mut header = Vec::with_capacity(size);
+    header.extend_from_slice(&(size as u32).to_be_bytes());
+    header.extend_from_slice(&[0; 4]);
+    header.extend_from_slice(&[2, 0x10, 0, 0]);
+    header.extend_from_slice(b"mntrRGB XYZ ");
+    header.extend_from_slice(&[0; 12]);
+    header.extend_from_slice(b"acsp");
+    header.extend_from_slice(&[0; 24]);
+    header.extend_from_slice(&0u32.to_be_bytes());
+    for v in D50 {
+        header.extend_from_slice(&s15fixed16(v));
+    }
+    header.resize(128, 0);
+    header.extend_from_slice(&table);
+    header.extend_from_slice(&body);
+    header
+}
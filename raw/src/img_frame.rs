@@ -12,6 +12,8 @@ use imageproc::drawing::draw_text_mut;
 use image::{imageops::FilterType};
 use imageproc::filter::gaussian_blur_f32;
 
+use crate::Rgb16Image;
+
 
 struct TextMetrics {
     width: u32,
@@ -96,31 +98,42 @@ fn measure_text(font: &FontArc, scale: PxScale, text: &str) -> TextMetrics {
 }
 
 
-pub fn gen_frame_img(main_img:Vec<u8>,width:u32,height:u32,text_list: &str,solid_bg:bool,shadow_show:bool,shadow:Option<f32>,font_path:&str) -> (Vec<u8>,u32,u32){
+pub fn gen_frame_img(main_img:Rgb16Image,text_list: &str,solid_bg:bool,shadow_show:bool,shadow:Option<f32>,font_path:&str) -> Rgb16Image{
 
-    let main_img = DynamicImage::ImageRgb8(ImageBuffer::from_raw(width,height,main_img).unwrap());
+    let main_img = DynamicImage::ImageRgb16(main_img);
     // main_img.save("aaabb.jpg");
 
-    let bg_img = get_frame(main_img,text_list,solid_bg,shadow_show,shadow,font_path);
-
-    // let out = 
-    (bg_img.to_vec(),bg_img.width(),bg_img.height())
-
+    get_frame(main_img,text_list,solid_bg,shadow_show,shadow,font_path).into_rgb16()
 }
 
+// 8 位颜色分量转换为 16 位
+fn rgba16(c: [u8; 4]) -> Rgba<u16> {
+    Rgba(c.map(|v| v as u16 * 257))
+}
 
-pub fn get_frame(img:DynamicImage,text_list:&str,solid_bg:bool,shadow_show:bool,shadow:Option<f32>,font_path:&str) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+/// 绘制相框，内部使用 16 位处理，输出与输入图像位深一致
+pub fn get_frame(img:DynamicImage,text_list:&str,solid_bg:bool,shadow_show:bool,shadow:Option<f32>,font_path:&str) -> DynamicImage {
+    let is_16bit = img.color().bytes_per_pixel() / img.color().channel_count() > 1;
     let (bg_h,bg_w) = calc_bg_img_size(img.height(), img.width(), img.height(), img.width(), 100.0, Some(img.height()));
 
     // println!("{} {}",bg_h,bg_w);
 
     
 
-    let (content_h,m_top) = calc_content_height(bg_h,img.height(),Some(5.0),0,true,Some(50));
+    // 阴影按短边计算，避免竖幅图像的阴影超出两侧边距
+    let portrait = img.height() > img.width();
+    let shadow_rate = 5.0 * img.width().min(img.height()) as f32 / img.height() as f32;
+    let (content_h,m_top) = calc_content_height(bg_h,img.height(),Some(shadow_rate),0,true,Some(50));
 
     let mut _content_offset_y = m_top;
 
-    let (bg_h,bg_w) = calc_bg_img_size(img.height(), img.width(), img.height(), img.width(), 100.0, Some(content_h.try_into().unwrap()));
+    // 竖幅图像两侧留出与顶部相同的边距
+    let main_img_w_rate = if portrait {
+        img.width() as f32 / (img.width() + 2 * m_top as u32) as f32 * 100.0
+    } else {
+        100.0
+    };
+    let (bg_h,bg_w) = calc_bg_img_size(img.height(), img.width(), img.height(), img.width(), main_img_w_rate, Some(content_h.try_into().unwrap()));
 
     // println!("{} {}",bg_h,bg_w);
 
@@ -140,7 +153,9 @@ pub fn get_frame(img:DynamicImage,text_list:&str,solid_bg:bool,shadow_show:bool,
 
     
 
-    let _bg_img = img.resize(bg_w, bg_h, FilterType::Nearest).to_rgba8();
+    let text_img = text_img.to_rgba16();
+
+    let _bg_img = img.resize(bg_w, bg_h, FilterType::Nearest).to_rgba16();
     let mut bg_img = gaussian_blur_f32(&_bg_img, 200.0);
 
     let content_offset_x = (bg_w - img.width()) /2;
@@ -158,16 +173,16 @@ pub fn get_frame(img:DynamicImage,text_list:&str,solid_bg:bool,shadow_show:bool,
         // println!("{}",average_brightness);
 
         let overlay_color = if average_brightness < 15 {
-            Rgba([180, 180, 180, 51]) // rgba(180, 180, 180, 0.2)
+            rgba16([180, 180, 180, 51]) // rgba(180, 180, 180, 0.2)
         } else if average_brightness < 20 {
-            Rgba([158, 158, 158, 51]) // rgba(158, 158, 158, 0.2)
+            rgba16([158, 158, 158, 51]) // rgba(158, 158, 158, 0.2)
         } else if average_brightness < 40 {
-            Rgba([128, 128, 128, 51]) // rgba(128, 128, 128, 0.2)
+            rgba16([128, 128, 128, 51]) // rgba(128, 128, 128, 0.2)
         } else {
-            Rgba([0, 0, 0, 51]) // rgba(0, 0, 0, 0.2)
+            rgba16([0, 0, 0, 51]) // rgba(0, 0, 0, 0.2)
         };
 
-        let mut _canvas = DynamicImage::new_rgba8(bg_w, bg_h).to_rgba8();
+        let mut _canvas = DynamicImage::new_rgba16(bg_w, bg_h).to_rgba16();
 
         draw_filled_rect_mut(
             &mut _canvas,
@@ -192,28 +207,33 @@ pub fn get_frame(img:DynamicImage,text_list:&str,solid_bg:bool,shadow_show:bool,
     let shadow_offset = 10; // 假设阴影偏移量为5
 
     let mut shadow = ImageBuffer::new(bg_w,bg_h);
-    draw_filled_rect_mut(&mut shadow, Rect::at((content_offset_x - shadow_offset) as i32,(content_offset_y - shadow_offset) as i32).of_size(img.width() + (2* shadow_offset) as u32,img.height() + (2* shadow_offset)), Rgba([0,0,0,128]));
+    draw_filled_rect_mut(&mut shadow, Rect::at((content_offset_x - shadow_offset) as i32,(content_offset_y - shadow_offset) as i32).of_size(img.width() + (2* shadow_offset) as u32,img.height() + (2* shadow_offset)), rgba16([0,0,0,128]));
     let shadowa = gaussian_blur_f32(&shadow, shadow_blur);
 
     image::imageops::overlay(&mut bg_img, &shadowa, 0,0);
 
     // 绘制主体图片到画布
-    image::imageops::overlay(&mut bg_img, &img, content_offset_x as i64, content_offset_y as i64);
+    image::imageops::overlay(&mut bg_img, &img.to_rgba16(), content_offset_x as i64, content_offset_y as i64);
 
     image::imageops::overlay(&mut bg_img, &text_img, text_offset_x as i64, text_offset_y as i64);
     // bg_img
-    DynamicImage::ImageRgba8(bg_img).to_rgb8()
+    let bg_img = DynamicImage::ImageRgba16(bg_img);
+    if is_16bit {
+        DynamicImage::ImageRgb16(bg_img.to_rgb16())
+    } else {
+        DynamicImage::ImageRgb8(bg_img.to_rgb8())
+    }
 }
 
 
-fn calc_average_brightness(img: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> u8 {
-    let mut sum = 0;
-    let mut count = 0;
+fn calc_average_brightness(img: &ImageBuffer<Rgba<u16>, Vec<u16>>) -> u8 {
+    let mut sum: u64 = 0;
+    let mut count: u64 = 0;
     for pixel in img.pixels() {
-        sum += (pixel[0] as u32 + pixel[1] as u32 + pixel[2] as u32) / 3;
+        sum += (pixel[0] as u64 + pixel[1] as u64 + pixel[2] as u64) / 3;
         count += 1;
     }
-    (sum / count) as u8
+    (sum / count / 257) as u8
 }
 
 fn calc_bg_img_size(reset_h:u32,reset_w:u32,h:u32,w:u32,main_img_w_rate:f32, height: Option<u32>) ->(u32,u32) {
@@ -0,0 +1,509 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+use rayon::prelude::*;
+use roxmltree::{Document, Node, ParsingOptions};
+
+use crate::error::RawError;
+use crate::geometry::sample_channel;
+use crate::options::{ColorSpace, LensCorrection};
+use crate::tone_map::{decode, encode};
+use crate::Myexif;
+
+/// 按镜头配置文件和手动参数校正暗角、畸变和横向色差
+///
+/// 畸变和色差坐标以短边的一半归一化，暗角以对角线的一半归一化，与 lensfun 一致。
+/// 没有考虑配置文件与相机画幅不同的情况
+pub(crate) fn correct_lens(
+    mut data: Vec<u16>,
+    width: usize,
+    height: usize,
+    correction: &LensCorrection,
+    exif: &Myexif,
+    color_space: ColorSpace,
+) -> Result<Vec<u16>, RawError> {
+    let database = Path::new(&correction.database);
+    let profile = if correction.profile && !exif.lens.is_empty() && database.exists() {
+        LensDatabase::cached(database)?
+            .find(&exif.lens, &exif.make, &exif.model)
+            .cloned()
+    } else {
+        None
+    };
+    let focal = exif.focal_length;
+    let mut distortion = profile
+        .as_ref()
+        .and_then(|p| interpolate(&p.distortion, focal))
+        .unwrap_or([0.0; 3]);
+    // 手动畸变使用 poly3 模型，桶形畸变的 k1 为负
+    distortion[1] -= 0.1 * correction.distortion.clamp(-100.0, 100.0) / 100.0;
+    let tca = profile
+        .as_ref()
+        .and_then(|p| interpolate(&p.tca, focal))
+        .unwrap_or([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+    let vignetting = profile
+        .as_ref()
+        .and_then(|p| p.vignetting_at(focal, exif.aperture))
+        .unwrap_or([0.0; 3]);
+    let manual_vignetting = correction.vignetting.clamp(-100.0, 100.0) / 100.0;
+
+    if vignetting != [0.0; 3] || manual_vignetting != 0.0 {
+        correct_vignetting(&mut data, width, height, vignetting, manual_vignetting, color_space);
+    }
+    if distortion != [0.0; 3] || tca != [1.0, 0.0, 0.0, 1.0, 0.0, 0.0] {
+        data = remap(&data, width, height, distortion, tca);
+    }
+    Ok(data)
+}
+
+fn correct_vignetting(
+    data: &mut [u16],
+    width: usize,
+    height: usize,
+    k: [f32; 3],
+    manual: f32,
+    color_space: ColorSpace,
+) {
+    let to_linear: Vec<f32> = (0..=u16::MAX)
+        .into_par_iter()
+        .map(|v| decode(v as f32 / 65535.0, color_space))
+        .collect();
+    let from_linear: Vec<u16> = (0..=u16::MAX)
+        .into_par_iter()
+        .map(|v| (encode(v as f32 / 65535.0, color_space) * 65535.0).round() as u16)
+        .collect();
+    let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+    let norm = 1.0 / (cx * cx + cy * cy).sqrt();
+
+    data.par_chunks_exact_mut(width * 3).enumerate().for_each(|(y, row)| {
+        let dy = (y as f32 + 0.5 - cy) * norm;
+        for (x, p) in row.chunks_exact_mut(3).enumerate() {
+            let dx = (x as f32 + 0.5 - cx) * norm;
+            let r2 = dx * dx + dy * dy;
+            let falloff = 1.0 + k[0] * r2 + k[1] * r2 * r2 + k[2] * r2 * r2 * r2;
+            // 手动补偿在角落达到 1 + manual 倍
+            let gain = (1.0 + manual * r2) / falloff.max(0.05);
+            for v in p.iter_mut() {
+                let linear = (to_linear[*v as usize] * gain).clamp(0.0, 1.0);
+                *v = from_linear[(linear * 65535.0).round() as usize];
+            }
+        }
+    });
+}
+
+/// 对每个输出像素计算在原图中的取样位置，红蓝通道额外按色差模型缩放
+fn remap(data: &[u16], width: usize, height: usize, distortion: [f32; 3], tca: [f32; 6]) -> Vec<u16> {
+    let [a, b, c] = distortion;
+    let factor = move |r: f32| a * r * r * r + b * r * r + c * r + 1.0 - a - b - c;
+    let channel_scale = move |r: f32, k: &[f32]| k[2] * r * r + k[1] * r + k[0];
+    let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+    let norm = cx.min(cy);
+    let scale = auto_scale(&factor, cx / norm, cy / norm);
+
+    let mut out = vec![0u16; data.len()];
+    out.par_chunks_exact_mut(width * 3).enumerate().for_each(|(y, row)| {
+        let dy = (y as f32 + 0.5 - cy) / norm * scale;
+        for (x, p) in row.chunks_exact_mut(3).enumerate() {
+            let dx = (x as f32 + 0.5 - cx) / norm * scale;
+            let ru = (dx * dx + dy * dy).sqrt();
+            let f = factor(ru);
+            let rd = ru * f;
+            let scales = [channel_scale(rd, &tca[..3]), 1.0, channel_scale(rd, &tca[3..])];
+            for (ch, v) in p.iter_mut().enumerate() {
+                let s = f * scales[ch];
+                let sx = dx * s * norm + cx - 0.5;
+                let sy = dy * s * norm + cy - 0.5;
+                *v = sample_channel(data, width, height, sx, sy, ch);
+            }
+        }
+    });
+    out
+}
+
+/// 校正枕形畸变时放大图像，避免边角取样到图像之外
+fn auto_scale(factor: &impl Fn(f32) -> f32, half_w: f32, half_h: f32) -> f32 {
+    let edges = [
+        (half_w * half_w + half_h * half_h).sqrt(),
+        half_w,
+        half_h,
+    ];
+    let fits = |s: f32| edges.iter().all(|&r| s * factor(s * r) <= 1.0 + 1e-4);
+    if fits(1.0) {
+        return 1.0;
+    }
+    let (mut lo, mut hi) = (0.5, 1.0);
+    for _ in 0..20 {
+        let mid = (lo + hi) / 2.0;
+        if fits(mid) {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+/// 按焦距在相邻的两组系数之间线性插值，超出范围时使用最近的一组
+fn interpolate<const N: usize>(entries: &[(f32, [f32; N])], focal: f32) -> Option<[f32; N]> {
+    let first = entries.first()?;
+    let last = entries.last()?;
+    if focal <= first.0 {
+        return Some(first.1);
+    }
+    if focal >= last.0 {
+        return Some(last.1);
+    }
+    let i = entries.partition_point(|e| e.0 <= focal);
+    let (f0, k0) = entries[i - 1];
+    let (f1, k1) = entries[i];
+    let t = (focal - f0) / (f1 - f0);
+    Some(std::array::from_fn(|j| k0[j] + (k1[j] - k0[j]) * t))
+}
+
+/// lensfun 数据库中的一个镜头
+#[derive(Debug, Clone, Default)]
+struct LensProfile {
+    maker: String,
+    model: String,
+    /// 镜头可用的卡口
+    mounts: Vec<String>,
+    /// 焦距和 ptlens 模型系数 a、b、c，poly3 模型转换为 b
+    distortion: Vec<(f32, [f32; 3])>,
+    /// 焦距和红、蓝通道的 poly3 色差系数 v、c、b，linear 模型只有 v
+    tca: Vec<(f32, [f32; 6])>,
+    /// 焦距、光圈和 pa 模型系数 k1、k2、k3
+    vignetting: Vec<(f32, f32, [f32; 3])>,
+}
+
+impl LensProfile {
+    fn from_element(lens: Node) -> Option<Self> {
+        let mut profile = LensProfile {
+            maker: child_text(lens, "maker").unwrap_or_default(),
+            model: child_text(lens, "model")?,
+            mounts: lens
+                .children()
+                .filter(|child| child.has_tag_name("mount"))
+                .map(|mount| normalize(&text(mount)))
+                .collect(),
+            ..Default::default()
+        };
+        for calibration in lens.children().filter(|child| child.has_tag_name("calibration")) {
+            for entry in calibration.children().filter(Node::is_element) {
+                let Some(focal) = attr_f32(entry, "focal") else {
+                    continue;
+                };
+                let k = |name| attr_f32(entry, name).unwrap_or(0.0);
+                match (entry.tag_name().name(), entry.attribute("model")) {
+                    ("distortion", Some("ptlens")) => {
+                        profile.distortion.push((focal, [k("a"), k("b"), k("c")]))
+                    }
+                    ("distortion", Some("poly3")) => profile.distortion.push((focal, [0.0, k("k1"), 0.0])),
+                    ("tca", Some("linear")) => {
+                        profile.tca.push((focal, [k("kr"), 0.0, 0.0, k("kb"), 0.0, 0.0]))
+                    }
+                    ("tca", Some("poly3")) => profile.tca.push((
+                        focal,
+                        [k("vr"), k("cr"), k("br"), k("vb"), k("cb"), k("bb")],
+                    )),
+                    ("vignetting", Some("pa")) => {
+                        if let Some(aperture) = attr_f32(entry, "aperture") {
+                            profile.vignetting.push((focal, aperture, [k("k1"), k("k2"), k("k3")]));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        profile.distortion.sort_by(|a, b| a.0.total_cmp(&b.0));
+        profile.tca.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Some(profile)
+    }
+
+    /// 取焦距和光圈最接近的一组暗角系数，比较时使用对数距离
+    fn vignetting_at(&self, focal: f32, aperture: f32) -> Option<[f32; 3]> {
+        let distance = |(f, a, _): &(f32, f32, [f32; 3])| {
+            (f.max(0.1) / focal.max(0.1)).ln().abs() + (a.max(0.1) / aperture.max(0.1)).ln().abs()
+        };
+        self.vignetting
+            .iter()
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+            .map(|v| v.2)
+    }
+}
+
+/// lensfun 数据库中的一台相机
+struct Camera {
+    maker: String,
+    model: String,
+    mount: String,
+}
+
+struct CachedDatabase {
+    path: PathBuf,
+    modified: SystemTime,
+    database: Arc<LensDatabase>,
+}
+
+struct LensDatabase {
+    lenses: Vec<LensProfile>,
+    cameras: Vec<Camera>,
+}
+
+impl LensDatabase {
+    /// 读取单个 XML 文件或目录中的所有 XML 文件，无法解析的文件会被跳过
+    fn load(path: &Path) -> Result<Self, RawError> {
+        let mut database = LensDatabase {
+            lenses: Vec::new(),
+            cameras: Vec::new(),
+        };
+        for file in database_files(path)? {
+            database.add_xml(&fs::read_to_string(file)?);
+        }
+        Ok(database)
+    }
+
+    /// 添加一个 XML 文件中的镜头和相机，文件无法解析时返回 false
+    fn add_xml(&mut self, text: &str) -> bool {
+        // 部分数据库文件带有 DOCTYPE 声明
+        let options = ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        };
+        let Ok(document) = Document::parse_with_options(text, options) else {
+            return false;
+        };
+        let root = document.root_element();
+        self.lenses.extend(
+            root.children()
+                .filter(|child| child.has_tag_name("lens"))
+                .filter_map(LensProfile::from_element),
+        );
+        self.cameras.extend(root.children().filter(|child| child.has_tag_name("camera")).filter_map(
+            |camera| {
+                Some(Camera {
+                    maker: normalize(&child_text(camera, "maker")?),
+                    model: normalize(&child_text(camera, "model")?),
+                    mount: normalize(&child_text(camera, "mount")?),
+                })
+            },
+        ));
+        true
+    }
+
+    /// 读取解析后的数据库，路径和其中文件的最新修改时间不变时直接使用上次的结果
+    fn cached(path: &Path) -> Result<Arc<Self>, RawError> {
+        static CACHE: OnceLock<Mutex<Option<CachedDatabase>>> = OnceLock::new();
+        let cache = CACHE.get_or_init(|| Mutex::new(None));
+        let modified = database_files(path)?
+            .iter()
+            .chain([&path.to_path_buf()])
+            .filter_map(|file| fs::metadata(file).and_then(|m| m.modified()).ok())
+            .max()
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        if let Some(cached) = &*cache.lock().unwrap() {
+            if cached.path == path && cached.modified == modified {
+                return Ok(cached.database.clone());
+            }
+        }
+        let database = Arc::new(LensDatabase::load(path)?);
+        *cache.lock().unwrap() = Some(CachedDatabase {
+            path: path.to_path_buf(),
+            modified,
+            database: database.clone(),
+        });
+        Ok(database)
+    }
+
+    /// 按型号查找镜头，只比较字母和数字；完全相同优先，其次是互相包含且最接近的型号
+    ///
+    /// 数据库中有对应相机时，优先选择卡口与相机一致的镜头，其次是厂商与相机相同的镜头
+    fn find(&self, lens: &str, camera_maker: &str, camera_model: &str) -> Option<&LensProfile> {
+        let key = normalize(lens);
+        if key.is_empty() {
+            return None;
+        }
+        let camera_maker = normalize(camera_maker);
+        let camera_model = normalize(camera_model);
+        let mounts: Vec<&str> = self
+            .cameras
+            .iter()
+            .filter(|c| {
+                c.model == camera_model
+                    && (camera_maker.contains(&c.maker) || c.maker.contains(&camera_maker))
+            })
+            .map(|c| c.mount.as_str())
+            .collect();
+        self.lenses
+            .iter()
+            .filter_map(|profile| {
+                let model = normalize(&profile.model);
+                let score = if model == key {
+                    0
+                } else if model.contains(&key) || key.contains(&model) {
+                    model.len().abs_diff(key.len())
+                } else {
+                    return None;
+                };
+                let other_mount = !profile.mounts.iter().any(|m| mounts.contains(&m.as_str()));
+                let other_maker = normalize(&profile.maker) != camera_maker;
+                Some(((other_mount, score, other_maker), profile))
+            })
+            .min_by_key(|(key, _)| *key)
+            .map(|(_, profile)| profile)
+    }
+}
+
+/// 数据库路径为目录时返回其中的所有 XML 文件
+fn database_files(path: &Path) -> Result<Vec<PathBuf>, RawError> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files: Vec<_> = fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("xml")))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// 子元素文本，优先使用没有 lang 属性的版本
+fn child_text(node: Node, name: &str) -> Option<String> {
+    node.children()
+        .filter(|child| child.has_tag_name(name))
+        .min_by_key(|child| child.has_attribute("lang"))
+        .map(|child| text(child).trim().to_string())
+}
+
+/// 元素中的全部文本，跳过注释
+fn text(node: Node) -> String {
+    node.descendants().filter_map(|n| n.is_text().then(|| n.text()).flatten()).collect()
+}
+
+fn attr_f32(node: Node, name: &str) -> Option<f32> {
+    node.attribute(name)?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 摘自 lensfun 数据库，包含注释、实体、CDATA 和多个卡口与校准数据
+    const DATABASE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE lensdatabase SYSTEM "lensfun-database.dtd">
+<lensdatabase version="2">
+    <!-- Sony E 卡口相机 -->
+    <camera>
+        <maker>Sony</maker>
+        <model>ILCE-7M3</model>
+        <mount>Sony E</mount>
+        <cropfactor>1</cropfactor>
+    </camera>
+    <camera>
+        <maker>Nikon Corporation</maker>
+        <maker lang="en">Nikon</maker>
+        <model>Nikon Z 6</model>
+        <mount>Nikon Z</mount>
+        <cropfactor>1</cropfactor>
+    </camera>
+
+    <lens>
+        <maker>Voigtl&#228;nder</maker>
+        <model>Voigtl&#xE4;nder Nokton 40mm f/1.2 Aspherical</model>
+        <model lang="de">Voigtländer Nokton 40mm 1:1.2</model>
+        <mount>Sony E</mount>
+        <mount>Leica M</mount>
+        <cropfactor>1</cropfactor>
+        <calibration>
+            <!-- Taken with Sony ILCE-7M3 -->
+            <distortion model="ptlens" focal='40' a="0.012" b="-0.034" c="0.005"/>
+            <tca model="poly3" focal="40" vr="1.0002" vb="0.9998" />
+        </calibration>
+        <calibration>
+            <vignetting model="pa" focal="40" aperture="1.2" distance="10" k1="-0.8" k2="0.3" k3="-0.1"/>
+            <vignetting model="pa" focal="40" aperture="4" distance="10" k1="-0.2" k2="0.05" k3="0"/>
+        </calibration>
+    </lens>
+
+    <lens>
+        <maker>Nikon</maker>
+        <model><![CDATA[Nikkor Z 24-70mm f/4 S]]></model>
+        <mount>Nikon Z</mount>
+        <calibration>
+            <distortion model="poly3" focal="70" k1="0.01"/>
+            <distortion model="poly3" focal="24" k1="-0.02"/>
+        </calibration>
+    </lens>
+
+    <lens>
+        <maker>Sigma &amp; Co</maker>
+        <model>Nikkor Z 24-70mm f/4 S</model>
+        <mount>Sony E</mount>
+        <calibration>
+            <distortion model="poly3" focal="24" k1="0.05"/>
+        </calibration>
+    </lens>
+</lensdatabase>
+"#;
+
+    fn database() -> LensDatabase {
+        let mut database = LensDatabase {
+            lenses: Vec::new(),
+            cameras: Vec::new(),
+        };
+        assert!(database.add_xml(DATABASE));
+        database
+    }
+
+    #[test]
+    fn parse_lensfun_xml() {
+        let database = database();
+        assert_eq!(database.cameras.len(), 2);
+        assert_eq!(database.cameras[1].maker, "nikoncorporation");
+        assert_eq!(database.cameras[1].mount, "nikonz");
+        assert_eq!(database.lenses.len(), 3);
+
+        let nokton = &database.lenses[0];
+        assert_eq!(nokton.maker, "Voigtländer");
+        assert_eq!(nokton.model, "Voigtländer Nokton 40mm f/1.2 Aspherical");
+        assert_eq!(nokton.mounts, ["sonye", "leicam"]);
+        assert_eq!(nokton.distortion, [(40.0, [0.012, -0.034, 0.005])]);
+        assert_eq!(nokton.tca, [(40.0, [1.0002, 0.0, 0.0, 0.9998, 0.0, 0.0])]);
+        assert_eq!(nokton.vignetting.len(), 2);
+        assert_eq!(nokton.vignetting_at(40.0, 4.0), Some([-0.2, 0.05, 0.0]));
+
+        let nikkor = &database.lenses[1];
+        assert_eq!(nikkor.model, "Nikkor Z 24-70mm f/4 S");
+        // 多组校准数据按焦距排序
+        assert_eq!(nikkor.distortion, [(24.0, [0.0, -0.02, 0.0]), (70.0, [0.0, 0.01, 0.0])]);
+        assert_eq!(database.lenses[2].maker, "Sigma & Co");
+    }
+
+    #[test]
+    fn find_prefers_camera_mount() {
+        let database = database();
+        let lens = "NIKKOR Z 24-70mm f/4 S";
+        let profile = database.find(lens, "NIKON CORPORATION", "NIKON Z 6").unwrap();
+        assert_eq!(profile.maker, "Nikon");
+        let profile = database.find(lens, "SONY", "ILCE-7M3").unwrap();
+        assert_eq!(profile.maker, "Sigma & Co");
+        let profile = database.find("Nokton 40mm f/1.2", "SONY", "ILCE-7M3").unwrap();
+        assert_eq!(profile.maker, "Voigtländer");
+        assert!(database.find("Unknown 50mm", "SONY", "ILCE-7M3").is_none());
+    }
+
+    #[test]
+    fn skip_invalid_xml() {
+        let mut database = database();
+        assert!(!database.add_xml("<lensdatabase><lens></lensdatabase>"));
+        assert_eq!(database.lenses.len(), 3);
+    }
+}
@@ -1,327 +1,270 @@
 use rayon::prelude::*;
-use std::{fs::File, io::{self, BufRead, BufReader}};
 
-const MAX_LEVEL: usize = 65; // 根据实际情况定义MAX_LEVEL
+use crate::options::LutInterpolation;
 
-#[derive(Debug, Clone, Copy)]
-struct RGBVec {
-    r: f32,
-    g: f32,
-    b: f32,
-}
+pub(crate) const MAX_LEVEL: usize = 65; // 根据实际情况定义MAX_LEVEL
 
+/// 3D LUT，数据连续存放，下标为 (r * lutsize + g) * lutsize + b
 #[derive(Debug)]
 pub struct LUT3DContext {
-    lut: Vec<Vec<Vec<RGBVec>>>,
+    lut: Vec<[f32; 3]>,
     lutsize: usize,
-    step: usize,
+    /// 输入范围，超出范围的值按边界取值
+    min: [f32; 3],
+    max: [f32; 3],
 }
 
-
 impl LUT3DContext {
-    fn new(lutsize: usize) -> Self {
+    /// 按 (r, g, b) 网格坐标逐点生成 LUT
+    pub(crate) fn from_fn(lutsize: usize, mut f: impl FnMut(usize, usize, usize) -> [f32; 3]) -> Self {
+        let mut lut = Vec::with_capacity(lutsize.pow(3));
+        for i in 0..lutsize {
+            for j in 0..lutsize {
+                for k in 0..lutsize {
+                    lut.push(f(i, j, k));
+                }
+            }
+        }
         LUT3DContext {
-            lut: vec![
-                vec![
-                    vec![
-                        RGBVec {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0
-                        };
-                        lutsize
-                    ];
-                    lutsize
-                ];
-                lutsize
-            ],
+            lut,
             lutsize,
-            step: 3 as usize,
+            min: [0.0; 3],
+            max: [1.0; 3],
         }
     }
-}
 
-pub fn parse_cube(filename: &str) -> io::Result<LUT3DContext> {
-    let file = File::open(filename)?;
-    let reader = BufReader::new(file).lines();
-    // let mut lut3d = None;
-    let mut min = [0.0f32; 3];
-    let mut max = [1.0f32; 3];
-    let mut lutsize = 0;
-    let mut rgbs = Vec::new();
+    pub(crate) fn with_range(mut self, min: [f32; 3], max: [f32; 3]) -> Self {
+        self.min = min;
+        self.max = max;
+        self
+    }
 
-    for line in reader {
-        let line = line?;
-        if line.starts_with("LUT_3D_SIZE ") {
-            let size = line[12..].parse::<usize>().unwrap();
-            if size < 2 || size > MAX_LEVEL {
-                println!("Too large or invalid 3D LUT size");
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "Invalid LUT size",
-                ));
-            }
-            lutsize = size;
-            continue;
-        }
-        if line.starts_with("DOMAIN_") {
-            if line.starts_with("DOMAIN_MIN ") {
-                let _m: Vec<&str> = line.split_whitespace().collect();
-                min[0] = _m[1].parse::<f32>().unwrap();
-                min[1] = _m[2].parse::<f32>().unwrap();
-                min[2] = _m[3].parse::<f32>().unwrap();
-            } else if line.starts_with("DOMAIN_MAX ") {
-                let _m: Vec<&str> = line.split_whitespace().collect();
-                max[0] = _m[1].parse::<f32>().unwrap();
-                max[1] = _m[2].parse::<f32>().unwrap();
-                max[2] = _m[3].parse::<f32>().unwrap();
-            }
-            continue;
-        }
+    /// 输入值 v 对应的网格坐标为 v * mul + add，v 的取值范围为 0 到 in_max
+    fn grid_mapping(&self, in_max: f32) -> [(f32, f32); 3] {
+        let scale = (self.lutsize - 1) as f32;
+        std::array::from_fn(|c| {
+            let mul = scale / (self.max[c] - self.min[c]);
+            (mul / in_max, -self.min[c] * mul)
+        })
+    }
 
-        let rgb_values: Vec<&str> = line.split_whitespace().collect();
-        if rgb_values.len() == 3 {
-            let r;
-            let g;
-            let b;
-            match rgb_values[0].parse::<f32>() {
-                Ok(_r) => {
-                    r = _r;
-                }
-                Err(_e) => {
-                    continue;
-                }
-            }
-            match rgb_values[1].parse::<f32>() {
-                Ok(_g) => {
-                    g = _g;
-                }
-                Err(_e) => {
-                    continue;
-                }
-            }
-            match rgb_values[2].parse::<f32>() {
-                Ok(_b) => {
-                    b = _b;
-                }
-                Err(_e) => {
-                    continue;
-                }
-            }
-            rgbs.push(RGBVec { r, g, b });
-        }
+    /// 将网格坐标限制在 LUT 范围内
+    #[inline]
+    fn clamp(&self, s: [f32; 3]) -> [f32; 3] {
+        let scale = (self.lutsize - 1) as f32;
+        s.map(|v| v.clamp(0.0, scale))
     }
-    let mut n = 0;
-    let mut lut3d_context = LUT3DContext::new(lutsize);
-    for k in 0..lutsize {
-        for j in 0..lutsize {
-            for i in 0..lutsize {
-                let vec = &mut lut3d_context.lut[i][j][k];
 
-                vec.r = rgbs[n].r * (max[0] - min[0]);
-                vec.g = rgbs[n].g * (max[1] - min[1]);
-                vec.b = rgbs[n].b * (max[2] - min[2]);
-                n = n + 1;
-            }
-        }
+    /// 网格坐标 s 所在单元的原点下标，以及 r、g、b 方向上相邻网格点的下标偏移和小数部分
+    #[inline]
+    fn cell(&self, s: [f32; 3]) -> (usize, [usize; 3], [f32; 3]) {
+        let size = self.lutsize;
+        let prev = s.map(|v| v as usize);
+        let stride = [size * size, size, 1];
+        let base = prev[0] * stride[0] + prev[1] * stride[1] + prev[2];
+        let step = std::array::from_fn(|c| if prev[c] + 1 < size { stride[c] } else { 0 });
+        let d = std::array::from_fn(|c| s[c] - prev[c] as f32);
+        (base, step, d)
     }
-    Ok(lut3d_context)
 }
 
-fn interp_tetrahedral(lut3d: &LUT3DContext, s: RGBVec) -> RGBVec {
-    let prev = [s.r as i32, s.g as i32, s.b as i32];
-    let next = [
-        std::cmp::min(s.r as i32 + 1, lut3d.lutsize as i32 - 1),
-        std::cmp::min(s.g as i32 + 1, lut3d.lutsize as i32 - 1),
-        std::cmp::min(s.b as i32 + 1, lut3d.lutsize as i32 - 1),
-    ];
+/// 1D LUT，每个通道在输入范围内均匀采样
+#[derive(Debug)]
+pub struct LUT1DContext {
+    curves: [Vec<f32>; 3],
+    min: [f32; 3],
+    max: [f32; 3],
+}
 
-    let d = RGBVec {
-        r: s.r - prev[0] as f32,
-        g: s.g - prev[1] as f32,
-        b: s.b - prev[2] as f32,
-    };
-    let c000 = lut3d.lut[prev[0] as usize][prev[1] as usize][prev[2] as usize];
-    let c111 = lut3d.lut[next[0] as usize][next[1] as usize][next[2] as usize];
-    let mut c = RGBVec {
-        r: 0.0,
-        g: 0.0,
-        b: 0.0,
-    };
-    if d.r > d.g {
-        if d.g > d.b {
-            let c100 = lut3d.lut[next[0] as usize][prev[1] as usize][prev[2] as usize];
-            let c110 = lut3d.lut[next[0] as usize][next[1] as usize][prev[2] as usize];
-            c.r = (1 as f32 - d.r) * c000.r
-                + (d.r - d.g) * c100.r
-                + (d.g - d.b) * c110.r
-                + (d.b) * c111.r;
-            c.g = (1 as f32 - d.r) * c000.g
-                + (d.r - d.g) * c100.g
-                + (d.g - d.b) * c110.g
-                + (d.b) * c111.g;
-            c.b = (1 as f32 - d.r) * c000.b
-                + (d.r - d.g) * c100.b
-                + (d.g - d.b) * c110.b
-                + (d.b) * c111.b;
-        } else if d.r > d.b {
-            let c100 = lut3d.lut[next[0] as usize][prev[1] as usize][prev[2] as usize];
-            let c101 = lut3d.lut[next[0] as usize][prev[1] as usize][next[2] as usize];
-            c.r = (1 as f32 - d.r) * c000.r
-                + (d.r - d.b) * c100.r
-                + (d.b - d.g) * c101.r
-                + (d.g) * c111.r;
-            c.g = (1 as f32 - d.r) * c000.g
-                + (d.r - d.b) * c100.g
-                + (d.b - d.g) * c101.g
-                + (d.g) * c111.g;
-            c.b = (1 as f32 - d.r) * c000.b
-                + (d.r - d.b) * c100.b
-                + (d.b - d.g) * c101.b
-                + (d.g) * c111.b;
-        } else {
-            let c001 = lut3d.lut[prev[0] as usize][prev[1] as usize][next[2] as usize];
-            let c101 = lut3d.lut[next[0] as usize][prev[1] as usize][next[2] as usize];
-            c.r = (1 as f32 - d.b) * c000.r
-                + (d.b - d.r) * c001.r
-                + (d.r - d.g) * c101.r
-                + (d.g) * c111.r;
-            c.g = (1 as f32 - d.b) * c000.g
-                + (d.b - d.r) * c001.g
-                + (d.r - d.g) * c101.g
-                + (d.g) * c111.g;
-            c.b = (1 as f32 - d.b) * c000.b
-                + (d.b - d.r) * c001.b
-                + (d.r - d.g) * c101.b
-                + (d.g) * c111.b;
-        }
-    } else {
-        if d.b > d.g {
-            let c001 = lut3d.lut[prev[0] as usize][prev[1] as usize][next[2] as usize];
-            let c011 = lut3d.lut[prev[0] as usize][next[1] as usize][next[2] as usize];
-            c.r = (1 as f32 - d.b) * c000.r
-                + (d.b - d.g) * c001.r
-                + (d.g - d.r) * c011.r
-                + (d.r) * c111.r;
-            c.g = (1 as f32 - d.b) * c000.g
-                + (d.b - d.g) * c001.g
-                + (d.g - d.r) * c011.g
-                + (d.r) * c111.g;
-            c.b = (1 as f32 - d.b) * c000.b
-                + (d.b - d.g) * c001.b
-                + (d.g - d.r) * c011.b
-                + (d.r) * c111.b;
-        } else if d.b > d.r {
-            let c010 = lut3d.lut[prev[0] as usize][next[1] as usize][prev[2] as usize];
-            let c011 = lut3d.lut[prev[0] as usize][next[1] as usize][next[2] as usize];
-            c.r = (1 as f32 - d.g) * c000.r
-                + (d.g - d.b) * c010.r
-                + (d.b - d.r) * c011.r
-                + (d.r) * c111.r;
-            c.g = (1 as f32 - d.g) * c000.g
-                + (d.g - d.b) * c010.g
-                + (d.b - d.r) * c011.g
-                + (d.r) * c111.g;
-            c.b = (1 as f32 - d.g) * c000.b
-                + (d.g - d.b) * c010.b
-                + (d.b - d.r) * c011.b
-                + (d.r) * c111.b;
-        } else {
-            let c010 = lut3d.lut[prev[0] as usize][next[1] as usize][prev[2] as usize];
-            let c110 = lut3d.lut[next[0] as usize][next[1] as usize][prev[2] as usize];
-            c.r = (1 as f32 - d.g) * c000.r
-                + (d.g - d.r) * c010.r
-                + (d.r - d.b) * c110.r
-                + (d.b) * c111.r;
-            c.g = (1 as f32 - d.g) * c000.g
-                + (d.g - d.r) * c010.g
-                + (d.r - d.b) * c110.g
-                + (d.b) * c111.g;
-            c.b = (1 as f32 - d.g) * c000.b
-                + (d.g - d.r) * c010.b
-                + (d.r - d.b) * c110.b
-                + (d.b) * c111.b;
-        }
+impl LUT1DContext {
+    /// 每条曲线至少需要两个采样点
+    pub(crate) fn new(curves: [Vec<f32>; 3], min: [f32; 3], max: [f32; 3]) -> Self {
+        LUT1DContext { curves, min, max }
+    }
+
+    fn eval(&self, c: usize, v: f32) -> f32 {
+        let curve = &self.curves[c];
+        let x = ((v - self.min[c]) / (self.max[c] - self.min[c])).clamp(0.0, 1.0)
+            * (curve.len() - 1) as f32;
+        let i = (x as usize).min(curve.len() - 2);
+        let t = x - i as f32;
+        curve[i] * (1.0 - t) + curve[i + 1] * t
     }
-    c
 }
 
-fn interp_nearest(lut3d: &LUT3DContext, s: RGBVec) -> RGBVec {
-    let v = lut3d.lut[(s.r + 0.5) as usize][(s.g + 0.5) as usize][(s.b + 0.5) as usize];
-    v
+/// 解析后的 LUT，由可选的 1D 曲线（shaper）和其后可选的 3D LUT 组成
+#[derive(Debug)]
+pub struct Lut {
+    pub title: Option<String>,
+    pub(crate) shaper: Option<LUT1DContext>,
+    pub(crate) lut3d: Option<LUT3DContext>,
 }
 
-fn clip_uint(a: f32) -> u8 {
-    let b = a as i32;
-    if b < 0 || b > 255 {
-        0 // 由于Rust是强类型语言，不需要显式地写((~a) >> 31)
+/// 按权重混合 4 个网格点
+#[inline]
+fn mix(lut3d: &LUT3DContext, points: [(usize, f32); 4]) -> [f32; 3] {
+    let mut c = [0.0; 3];
+    for (i, w) in points {
+        let v = lut3d.lut[i];
+        c[0] += w * v[0];
+        c[1] += w * v[1];
+        c[2] += w * v[2];
+    }
+    c
+}
+
+#[inline]
+fn interp_tetrahedral(lut3d: &LUT3DContext, s: [f32; 3]) -> [f32; 3] {
+    let (c000, [dr, dg, db], [r, g, b]) = lut3d.cell(s);
+    let c111 = c000 + dr + dg + db;
+    if r > g {
+        if g > b {
+            mix(lut3d, [(c000, 1.0 - r), (c000 + dr, r - g), (c000 + dr + dg, g - b), (c111, b)])
+        } else if r > b {
+            mix(lut3d, [(c000, 1.0 - r), (c000 + dr, r - b), (c000 + dr + db, b - g), (c111, g)])
+        } else {
+            mix(lut3d, [(c000, 1.0 - b), (c000 + db, b - r), (c000 + dr + db, r - g), (c111, g)])
+        }
+    } else if b > g {
+        mix(lut3d, [(c000, 1.0 - b), (c000 + db, b - g), (c000 + dg + db, g - r), (c111, r)])
+    } else if b > r {
+        mix(lut3d, [(c000, 1.0 - g), (c000 + dg, g - b), (c000 + dg + db, b - r), (c111, r)])
     } else {
-        b as u8
+        mix(lut3d, [(c000, 1.0 - g), (c000 + dg, g - r), (c000 + dr + dg, r - b), (c111, b)])
     }
 }
 
-pub fn interp_8_tetrahedral(
-    lut3d: LUT3DContext,
-    indata: Vec<u8>,
-    width: i32,
-    colors: i32,
-) -> Vec<u8> {
-    let nbits = 8;
-    let step = lut3d.step;
-    let r = 0;
-    let g = 1;
-    let b = 2;
-    // let a = 0;
-    let mut outdata = vec![0; indata.len()];
-    let scale = (1.0 / ((1 << nbits) - 1) as f32) * (lut3d.lutsize - 1) as f32;
-    let linesize = width * colors;
-    // let start = Instant::now();
-    //     // 计算每个像素的索引
-    outdata
-        .par_chunks_mut(linesize as usize)
-        .enumerate()
-        .for_each(|(y, out_chunk)| {
-            for x in (0..width * step as i32).step_by(step as usize) {
-                let scaled_rgb = RGBVec {
-                    r: indata[(y as i32 * linesize + x + r) as usize] as f32 * scale,
-                    g: indata[(y as i32 * linesize + x + g) as usize] as f32 * scale,
-                    b: indata[(y as i32 * linesize + x + b) as usize] as f32 * scale,
-                };
-                let vec = interp_tetrahedral(&lut3d, scaled_rgb);
-                out_chunk[(x + r) as usize] = clip_uint(vec.r * ((1 << nbits) - 1) as f32);
-                out_chunk[(x + g) as usize] = clip_uint(vec.g * ((1 << nbits) - 1) as f32);
-                out_chunk[(x + b) as usize] = clip_uint(vec.b * ((1 << nbits) - 1) as f32);
-            }
-        });
+#[inline]
+fn interp_trilinear(lut3d: &LUT3DContext, s: [f32; 3]) -> [f32; 3] {
+    let (c000, [dr, dg, db], [r, g, b]) = lut3d.cell(s);
+    let lerp = |a: usize, b: usize, t: f32| -> [f32; 3] {
+        let (a, b) = (lut3d.lut[a], lut3d.lut[b]);
+        std::array::from_fn(|c| a[c] + (b[c] - a[c]) * t)
+    };
+    let lerp3 = |a: [f32; 3], b: [f32; 3], t: f32| -> [f32; 3] {
+        std::array::from_fn(|c| a[c] + (b[c] - a[c]) * t)
+    };
+    let c00 = lerp(c000, c000 + db, b);
+    let c01 = lerp(c000 + dg, c000 + dg + db, b);
+    let c10 = lerp(c000 + dr, c000 + dr + db, b);
+    let c11 = lerp(c000 + dr + dg, c000 + dr + dg + db, b);
+    lerp3(lerp3(c00, c01, g), lerp3(c10, c11, g), r)
+}
+
+#[inline]
+fn interp_nearest(lut3d: &LUT3DContext, s: [f32; 3]) -> [f32; 3] {
+    let size = lut3d.lutsize;
+    let [r, g, b] = s.map(|v| ((v + 0.5) as usize).min(size - 1));
+    lut3d.lut[(r * size + g) * size + b]
+}
 
-    // let duration = start.elapsed();
+#[inline]
+fn clip_uint(a: f32) -> u8 {
+    // 先限制范围再加 0.5 截断，等同于四舍五入，比 f32::round 快
+    (a.clamp(0.0, 255.0) + 0.5) as u8
+}
 
-    // // 输出运行时间
-    // println!("运行时间: {:?}", duration);
+#[inline]
+fn clip_uint16(a: f32) -> u16 {
+    (a.clamp(0.0, 65535.0) + 0.5) as u16
+}
 
-    // let start = Instant::now();
+/// 可进行 lut 插值的通道类型
+pub trait LutSample: Copy + Send + Sync {
+    const MAX: f32;
+    fn to_f32(self) -> f32;
+    fn from_f32(v: f32) -> Self;
+}
 
-    // for y in 0..height {
-    //     for x in (0..width * step as i32).step_by(step) {
-    //         let scaled_rgb = RGBVec {
-    //             r: indata[(y * linesize + x + r) as usize] as f32 * scale,
-    //             g: indata[(y * linesize + x + g) as usize] as f32 * scale,
-    //             b: indata[(y * linesize + x + b) as usize] as f32 * scale,
-    //         };
-    //         let _vec = interp_tetrahedral(&lut3d, scaled_rgb);
-    //         outdata[(y * linesize + x + r) as usize] =
-    //             clip_uint(_vec.r * ((1 << nbits) - 1) as f32);
-    //         outdata[(y * linesize + x + g) as usize] =
-    //             clip_uint(_vec.g * ((1 << nbits) - 1) as f32);
-    //         outdata[(y * linesize + x + b) as usize] =
-    //             clip_uint(_vec.b * ((1 << nbits) - 1) as f32);
-    //     }
-    // }
+impl LutSample for u8 {
+    const MAX: f32 = 255.0;
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+    fn from_f32(v: f32) -> Self {
+        clip_uint(v)
+    }
+}
 
-    // let duration = start.elapsed();
+impl LutSample for u16 {
+    const MAX: f32 = 65535.0;
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+    fn from_f32(v: f32) -> Self {
+        clip_uint16(v)
+    }
+}
 
-    // // 输出运行时间
-    // println!("运行时间: {:?}", duration);
+impl Lut {
+    /// 对交错存放的图像数据应用 lut，每个像素 channels 个通道，前三个为 RGB，
+    /// 其余通道（如 alpha）保持不变
+    ///
+    /// intensity 为 LUT 输出与原图的混合比例，范围 0-1
+    pub fn apply<T: LutSample>(
+        &self,
+        indata: &[T],
+        width: usize,
+        channels: usize,
+        interpolation: LutInterpolation,
+        intensity: f32,
+    ) -> Vec<T> {
+        // 插值方式在循环外分派，每种方式单独展开一个循环
+        match interpolation {
+            LutInterpolation::Nearest => self.apply_with(indata, width, channels, intensity, interp_nearest),
+            LutInterpolation::Trilinear => self.apply_with(indata, width, channels, intensity, interp_trilinear),
+            LutInterpolation::Tetrahedral => {
+                self.apply_with(indata, width, channels, intensity, interp_tetrahedral)
+            }
+        }
+    }
 
-    outdata
+    fn apply_with<T: LutSample>(
+        &self,
+        indata: &[T],
+        width: usize,
+        channels: usize,
+        intensity: f32,
+        interp: impl Fn(&LUT3DContext, [f32; 3]) -> [f32; 3] + Sync,
+    ) -> Vec<T> {
+        let mut outdata = indata.to_vec();
+        if channels < 3 || width == 0 {
+            return outdata;
+        }
+        // 1D 曲线预先展开为以通道值为下标的表
+        let curves: Option<[Vec<f32>; 3]> = self.shaper.as_ref().map(|shaper| {
+            std::array::from_fn(|c| {
+                (0..=T::MAX as usize)
+                    .map(|v| shaper.eval(c, v as f32 / T::MAX))
+                    .collect()
+            })
+        });
+        // 有 1D 曲线时 3D LUT 的输入为曲线输出，否则为通道值
+        let grid = self.lut3d.as_ref().map(|lut3d| {
+            (lut3d, lut3d.grid_mapping(if curves.is_some() { 1.0 } else { T::MAX }))
+        });
+        outdata
+            .par_chunks_mut(width * channels)
+            .for_each(|row| {
+                for pixel in row.chunks_exact_mut(channels) {
+                    let input: [f32; 3] = std::array::from_fn(|c| pixel[c].to_f32());
+                    let rgb = match &curves {
+                        Some(curves) => std::array::from_fn(|c| curves[c][input[c] as usize]),
+                        None => input,
+                    };
+                    let rgb = match &grid {
+                        Some((lut3d, mapping)) => interp(
+                            lut3d,
+                            lut3d.clamp(std::array::from_fn(|c| rgb[c] * mapping[c].0 + mapping[c].1)),
+                        ),
+                        None if curves.is_some() => rgb,
+                        None => rgb.map(|v| v / T::MAX),
+                    };
+                    for c in 0..3 {
+                        pixel[c] = T::from_f32(input[c] + (rgb[c] * T::MAX - input[c]) * intensity);
+                    }
+                }
+            });
+        outdata
+    }
 }
@@ -0,0 +1,84 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+use crate::error::LutError;
+use crate::lut3d::Lut;
+use crate::lut_loader::LutLoader;
+
+/// 默认缓存的 lut 数量，65 级 3D LUT 约占 3MB
+const DEFAULT_CAPACITY: usize = 16;
+
+struct Entry {
+    path: PathBuf,
+    modified: SystemTime,
+    lut: Arc<Lut>,
+}
+
+/// 解析后的 lut 缓存，以文件路径和修改时间为键，超出容量时淘汰最久未使用的项
+///
+/// 文件修改时间变化后自动重新解析，重新上传同名 lut 时也可调用 invalidate 立即失效
+pub struct LutCache {
+    capacity: usize,
+    /// 按最近使用排序，最近使用的在末尾
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl LutCache {
+    pub fn new(capacity: usize) -> Self {
+        LutCache {
+            capacity: capacity.max(1),
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 进程内共享的缓存，raw_process 等转换函数通过它加载 lut
+    pub fn global() -> &'static LutCache {
+        static CACHE: OnceLock<LutCache> = OnceLock::new();
+        CACHE.get_or_init(|| LutCache::new(DEFAULT_CAPACITY))
+    }
+
+    /// 读取缓存中的 lut，不存在或文件已修改时重新解析
+    pub fn get(&self, path: impl AsRef<Path>) -> Result<Arc<Lut>, LutError> {
+        let path = path.as_ref();
+        let Ok(modified) = fs::metadata(path)?.modified() else {
+            // 文件系统不支持修改时间时无法判断是否过期，不缓存
+            return Ok(Arc::new(LutLoader::load(path)?));
+        };
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if let Some(i) = entries.iter().position(|e| e.path == path) {
+                let entry = entries.remove(i);
+                if entry.modified == modified {
+                    let lut = entry.lut.clone();
+                    entries.push(entry);
+                    return Ok(lut);
+                }
+            }
+        }
+        // 解析时不持有锁，同一文件并发加载时以后完成的为准
+        let lut = Arc::new(LutLoader::load(path)?);
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|e| e.path != path);
+        if entries.len() >= self.capacity {
+            entries.remove(0);
+        }
+        entries.push(Entry {
+            path: path.to_path_buf(),
+            modified,
+            lut: lut.clone(),
+        });
+        Ok(lut)
+    }
+
+    /// 移除指定文件的缓存
+    pub fn invalidate(&self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        self.entries.lock().unwrap().retain(|e| e.path != path);
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
@@ -0,0 +1,587 @@
+use std::{fs, path::Path};
+
+use crate::error::LutError;
+use crate::lut3d::{Lut, LUT1DContext, LUT3DContext, MAX_LEVEL};
+
+/// 1D LUT 的最大采样点数
+const MAX_1D_SIZE: usize = 65536;
+/// Hald CLUT 的最大级别，level 16 的图像边长为 4096
+const MAX_HALD_LEVEL: usize = 16;
+/// 非均匀采样的 1D 曲线重采样后的点数
+const RESAMPLE_SIZE: usize = 1024;
+
+/// LUT 文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LutFormat {
+    /// Adobe / Resolve .cube，支持 3D、1D 以及 1D shaper 加 3D
+    Cube,
+    /// Autodesk .3dl
+    ThreeDl,
+    /// Hald CLUT 图像
+    Hald,
+    /// Cinespace .csp
+    Csp,
+}
+
+impl LutFormat {
+    /// 根据扩展名识别格式，Hald CLUT 支持 png 和 tiff
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_lowercase().as_str() {
+            "cube" => Some(LutFormat::Cube),
+            "3dl" => Some(LutFormat::ThreeDl),
+            "csp" => Some(LutFormat::Csp),
+            "png" | "tif" | "tiff" => Some(LutFormat::Hald),
+            _ => None,
+        }
+    }
+
+    /// 根据文件内容判断格式
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if image::guess_format(data).is_ok() {
+            return Some(LutFormat::Hald);
+        }
+        let text = std::str::from_utf8(data).ok()?;
+        if text.trim_start().starts_with("CSPLUTV100") {
+            return Some(LutFormat::Csp);
+        }
+        if text.contains("LUT_3D_SIZE") || text.contains("LUT_1D_SIZE") {
+            return Some(LutFormat::Cube);
+        }
+        // 3dl 没有固定的文件头，第一行为网格输入点或 3DMESH
+        let (_, first) = content_lines(text).next()?;
+        if first.starts_with("3DMESH") || first.split_whitespace().all(|v| v.parse::<f32>().is_ok()) {
+            return Some(LutFormat::ThreeDl);
+        }
+        None
+    }
+}
+
+/// 读取各种格式的 LUT 文件，统一转换为 1D 曲线加 3D LUT 的形式
+pub struct LutLoader;
+
+impl LutLoader {
+    /// 读取 LUT 文件，优先按扩展名识别格式，无法识别时根据内容判断
+    pub fn load(path: impl AsRef<Path>) -> Result<Lut, LutError> {
+        let path = path.as_ref();
+        let data = fs::read(path)?;
+        let format = path
+            .extension()
+            .and_then(|ext| LutFormat::from_extension(&ext.to_string_lossy()))
+            .or_else(|| LutFormat::detect(&data))
+            .ok_or(LutError::UnknownFormat)?;
+        Self::parse(&data, format)
+    }
+
+    /// 按指定格式解析 LUT 数据
+    pub fn parse(data: &[u8], format: LutFormat) -> Result<Lut, LutError> {
+        match format {
+            LutFormat::Cube => parse_cube(text(data)?),
+            LutFormat::ThreeDl => parse_3dl(text(data)?),
+            LutFormat::Hald => parse_hald(data),
+            LutFormat::Csp => parse_csp(text(data)?),
+        }
+    }
+}
+
+fn text(data: &[u8]) -> Result<&str, LutError> {
+    std::str::from_utf8(data).map_err(|e| LutError::invalid(format!("not a text file: {}", e)))
+}
+
+/// 去掉空行和 # 开头的注释行，返回行号和内容
+fn content_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+}
+
+fn parse_number(line: usize, v: &str) -> Result<f32, LutError> {
+    match v.parse::<f32>() {
+        Ok(v) if v.is_finite() => Ok(v),
+        _ => Err(LutError::at(line, format!("invalid number '{}'", v))),
+    }
+}
+
+fn parse_floats(line: usize, text: &str) -> Result<Vec<f32>, LutError> {
+    text.split_whitespace().map(|v| parse_number(line, v)).collect()
+}
+
+fn parse_rgb(line: usize, text: &str) -> Result<[f32; 3], LutError> {
+    match parse_floats(line, text)?[..] {
+        [r, g, b] => Ok([r, g, b]),
+        ref values => Err(LutError::at(line, format!("expected 3 values, found {}", values.len()))),
+    }
+}
+
+fn parse_range(line: usize, text: &str) -> Result<(f32, f32), LutError> {
+    match parse_floats(line, text)?[..] {
+        [min, max] if max > min => Ok((min, max)),
+        [_, _] => Err(LutError::at(line, "input range maximum must be greater than minimum")),
+        ref values => Err(LutError::at(line, format!("expected 2 values, found {}", values.len()))),
+    }
+}
+
+fn parse_size(line: usize, keyword: &str, text: &str, max: usize) -> Result<usize, LutError> {
+    match text.parse::<usize>() {
+        Ok(size) if (2..=max).contains(&size) => Ok(size),
+        _ => Err(LutError::at(
+            line,
+            format!("{} must be an integer between 2 and {}, found '{}'", keyword, max, text),
+        )),
+    }
+}
+
+/// .cube 文件，1D 和 3D 同时存在时 1D 数据在前，作为 3D LUT 的 shaper
+///
+/// 关键字必须位于数据之前且不能重复，数据行数必须与声明的尺寸一致。
+/// DOMAIN_MIN/DOMAIN_MAX 为第一级 LUT 的输入范围，Resolve 的 LUT_*_INPUT_RANGE 优先
+fn parse_cube(text: &str) -> Result<Lut, LutError> {
+    let mut title = None;
+    let mut size_1d = None;
+    let mut size_3d = None;
+    let mut domain_min = None;
+    let mut domain_max = None;
+    let mut range_1d = None;
+    let mut range_3d = None;
+    let mut rows: Vec<[f32; 3]> = Vec::new();
+
+    for (n, line) in content_lines(text) {
+        let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        if !keyword.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            rows.push(parse_rgb(n, line)?);
+            continue;
+        }
+        if !rows.is_empty() {
+            return Err(LutError::at(n, format!("keyword {} after table data", keyword)));
+        }
+        let duplicate = match keyword {
+            "TITLE" => match rest.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+                Some(t) => title.replace(t.to_string()).is_some(),
+                None => return Err(LutError::at(n, "TITLE must be a quoted string")),
+            },
+            "LUT_1D_SIZE" => size_1d.replace(parse_size(n, keyword, rest, MAX_1D_SIZE)?).is_some(),
+            "LUT_3D_SIZE" => size_3d.replace(parse_size(n, keyword, rest, MAX_LEVEL)?).is_some(),
+            "DOMAIN_MIN" => domain_min.replace((n, parse_rgb(n, rest)?)).is_some(),
+            "DOMAIN_MAX" => domain_max.replace((n, parse_rgb(n, rest)?)).is_some(),
+            "LUT_1D_INPUT_RANGE" => range_1d.replace(parse_range(n, rest)?).is_some(),
+            "LUT_3D_INPUT_RANGE" => range_3d.replace(parse_range(n, rest)?).is_some(),
+            // Resolve 的视频范围标记，不影响数据
+            "LUT_IN_VIDEO_RANGE" | "LUT_OUT_VIDEO_RANGE" => false,
+            _ => return Err(LutError::at(n, format!("unknown keyword {}", keyword))),
+        };
+        if duplicate {
+            return Err(LutError::at(n, format!("duplicate {}", keyword)));
+        }
+    }
+
+    let size_1d = size_1d.unwrap_or(0);
+    let size_3d = size_3d.unwrap_or(0);
+    if size_1d == 0 && size_3d == 0 {
+        return Err(LutError::invalid("missing LUT_1D_SIZE or LUT_3D_SIZE"));
+    }
+    let expected = size_1d + size_3d.pow(3);
+    if rows.len() != expected {
+        return Err(LutError::invalid(format!(
+            "expected {} table rows, found {}",
+            expected,
+            rows.len()
+        )));
+    }
+    let min = domain_min.map_or([0.0; 3], |(_, min)| min);
+    let max = domain_max.map_or([1.0; 3], |(_, max)| max);
+    if let Some(c) = (0..3).find(|&c| max[c] <= min[c]) {
+        let line = domain_max.or(domain_min).map_or(0, |(n, _)| n);
+        return Err(LutError::at(
+            line,
+            format!("DOMAIN_MAX must be greater than DOMAIN_MIN ({} <= {})", max[c], min[c]),
+        ));
+    }
+
+    let shaper = (size_1d > 0).then(|| {
+        let (min, max) = range_1d.map_or((min, max), |(lo, hi)| ([lo; 3], [hi; 3]));
+        let curves = std::array::from_fn(|c| rows[..size_1d].iter().map(|row| row[c]).collect());
+        LUT1DContext::new(curves, min, max)
+    });
+    let lut3d = (size_3d > 0).then(|| {
+        let (min, max) = match range_3d {
+            Some((lo, hi)) => ([lo; 3], [hi; 3]),
+            None if size_1d == 0 => (min, max),
+            None => ([0.0; 3], [1.0; 3]),
+        };
+        let rows = &rows[size_1d..];
+        // 红色变化最快
+        LUT3DContext::from_fn(size_3d, |i, j, k| rows[(k * size_3d + j) * size_3d + i])
+            .with_range(min, max)
+    });
+    Ok(Lut { title, shaper, lut3d })
+}
+
+/// .3dl 文件，第一行为网格输入点，数据为整数且蓝色变化最快，输出位深由 Mesh 行或最大值推断
+fn parse_3dl(text: &str) -> Result<Lut, LutError> {
+    let mut size = None;
+    let mut out_bits = None;
+    let mut rows = Vec::new();
+
+    for (n, line) in content_lines(text) {
+        let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match keyword {
+            "3DMESH" | "LUT8" | "gamma" => continue,
+            "Mesh" => match parse_floats(n, rest)?[..] {
+                [in_bits, bits] if (1.0..=6.0).contains(&in_bits) && (1.0..=16.0).contains(&bits) => {
+                    size = Some((1usize << in_bits as u32) + 1);
+                    out_bits = Some(bits as u32);
+                }
+                _ => return Err(LutError::at(n, format!("invalid mesh '{}'", rest))),
+            },
+            _ => {
+                let values = parse_floats(n, line)?;
+                match values[..] {
+                    [r, g, b] => rows.push([r, g, b]),
+                    _ if size.is_none() && rows.is_empty() => size = Some(values.len()),
+                    _ => return Err(LutError::at(n, format!("expected 3 values, found {}", values.len()))),
+                }
+            }
+        }
+    }
+    let size = size.unwrap_or_else(|| (rows.len() as f64).cbrt().round() as usize);
+    if !(2..=MAX_LEVEL).contains(&size) {
+        return Err(LutError::invalid(format!("invalid LUT size {}", size)));
+    }
+    if rows.len() != size.pow(3) {
+        return Err(LutError::invalid(format!(
+            "expected {} table rows, found {}",
+            size.pow(3),
+            rows.len()
+        )));
+    }
+    let scale = match out_bits {
+        Some(bits) => ((1u32 << bits) - 1) as f32,
+        None => {
+            let max = rows.iter().flatten().fold(0.0f32, |a, &b| a.max(b));
+            if max <= 1.0 {
+                1.0
+            } else {
+                ((max as u32 + 1).next_power_of_two() - 1) as f32
+            }
+        }
+    };
+    let lut3d = LUT3DContext::from_fn(size, |i, j, k| {
+        rows[(i * size + j) * size + k].map(|v| v / scale)
+    });
+    Ok(Lut {
+        title: None,
+        shaper: None,
+        lut3d: Some(lut3d),
+    })
+}
+
+/// Hald CLUT 图像，边长为 level³ 的正方形，对应大小为 level² 的 3D LUT，红色变化最快
+///
+/// 大小超过 MAX_LEVEL 时（level 9 及以上）按三线性插值降采样到 MAX_LEVEL
+fn parse_hald(data: &[u8]) -> Result<Lut, LutError> {
+    let img = image::load_from_memory(data)
+        .map_err(|e| LutError::invalid(e.to_string()))?
+        .to_rgb32f();
+    let (width, height) = img.dimensions();
+    let level = (width as f64).cbrt().round() as usize;
+    let size = level * level;
+    if width != height || level.pow(3) != width as usize || size < 2 {
+        return Err(LutError::invalid(format!("invalid Hald CLUT size {}x{}", width, height)));
+    }
+    if level > MAX_HALD_LEVEL {
+        return Err(LutError::invalid(format!(
+            "Hald CLUT level {} is too large, the maximum is {}",
+            level, MAX_HALD_LEVEL
+        )));
+    }
+    let pixels = img.as_raw();
+    let at = |i: usize, j: usize, k: usize| {
+        let n = ((k * size + j) * size + i) * 3;
+        [pixels[n], pixels[n + 1], pixels[n + 2]]
+    };
+    let lut3d = if size <= MAX_LEVEL {
+        LUT3DContext::from_fn(size, at)
+    } else {
+        let scale = (size - 1) as f32 / (MAX_LEVEL - 1) as f32;
+        // 降采样后的网格点在原网格中所在的单元和小数部分
+        let axis = |i: usize| {
+            let x = i as f32 * scale;
+            let i0 = (x as usize).min(size - 2);
+            (i0, x - i0 as f32)
+        };
+        LUT3DContext::from_fn(MAX_LEVEL, |i, j, k| {
+            let (r, g, b) = (axis(i), axis(j), axis(k));
+            let mut c = [0.0; 3];
+            for (di, wr) in [(0, 1.0 - r.1), (1, r.1)] {
+                for (dj, wg) in [(0, 1.0 - g.1), (1, g.1)] {
+                    for (dk, wb) in [(0, 1.0 - b.1), (1, b.1)] {
+                        let v = at(r.0 + di, g.0 + dj, b.0 + dk);
+                        let w = wr * wg * wb;
+                        c = std::array::from_fn(|n| c[n] + w * v[n]);
+                    }
+                }
+            }
+            c
+        })
+    };
+    Ok(Lut {
+        title: None,
+        shaper: None,
+        lut3d: Some(lut3d),
+    })
+}
+
+/// .csp 文件，每个通道先经过输入点任意分布的 pre-LUT，3D 数据红色变化最快
+fn parse_csp(text: &str) -> Result<Lut, LutError> {
+    let mut lines = content_lines(text);
+    match lines.next() {
+        Some((_, "CSPLUTV100")) => {}
+        Some((n, _)) => return Err(LutError::at(n, "missing CSPLUTV100 header")),
+        None => return Err(LutError::invalid("empty file")),
+    }
+    let is_3d = match lines.next() {
+        Some((_, "3D")) => true,
+        Some((_, "1D")) => false,
+        Some((n, _)) => return Err(LutError::at(n, "expected 1D or 3D")),
+        None => return Err(LutError::invalid("unexpected end of file")),
+    };
+    let mut tokens = Vec::new();
+    let mut metadata = false;
+    for (n, line) in lines {
+        match line {
+            "BEGIN METADATA" => metadata = true,
+            "END METADATA" => metadata = false,
+            _ if metadata => {}
+            _ => tokens.extend(line.split_whitespace().map(|v| (n, v))),
+        }
+    }
+    let mut values = tokens.into_iter().map(|(n, v)| parse_number(n, v).map(|v| (n, v)));
+    let mut next = || {
+        values
+            .next()
+            .unwrap_or_else(|| Err(LutError::invalid("unexpected end of file")))
+    };
+
+    let mut curves: [Vec<f32>; 3] = Default::default();
+    let mut min = [0.0f32; 3];
+    let mut max = [1.0f32; 3];
+    for c in 0..3 {
+        let (line, n) = next()?;
+        let n = n as usize;
+        if !(2..=MAX_1D_SIZE).contains(&n) {
+            return Err(LutError::at(line, format!("invalid pre-LUT size {}", n)));
+        }
+        let inputs = (0..n).map(|_| next().map(|(_, v)| v)).collect::<Result<Vec<f32>, _>>()?;
+        let outputs = (0..n).map(|_| next().map(|(_, v)| v)).collect::<Result<Vec<f32>, _>>()?;
+        if inputs.windows(2).any(|w| w[1] < w[0]) || inputs[n - 1] <= inputs[0] {
+            return Err(LutError::at(line, "pre-LUT inputs must be increasing"));
+        }
+        // 输入点不一定均匀分布，重采样为均匀的曲线
+        (min[c], max[c]) = (inputs[0], inputs[n - 1]);
+        curves[c] = (0..RESAMPLE_SIZE)
+            .map(|i| {
+                let x = min[c] + (max[c] - min[c]) * i as f32 / (RESAMPLE_SIZE - 1) as f32;
+                let i = inputs.partition_point(|&v| v <= x).clamp(1, n - 1);
+                let (x0, x1) = (inputs[i - 1], inputs[i]);
+                let t = if x1 > x0 { ((x - x0) / (x1 - x0)).clamp(0.0, 1.0) } else { 0.0 };
+                outputs[i - 1] * (1.0 - t) + outputs[i] * t
+            })
+            .collect();
+    }
+    if !is_3d {
+        // 1D 表紧跟在 pre-LUT 之后，输入范围为 0-1，与 pre-LUT 合并为一条曲线
+        let (line, n) = next()?;
+        let n = n as usize;
+        if !(2..=MAX_1D_SIZE).contains(&n) {
+            return Err(LutError::at(line, format!("invalid 1D LUT size {}", n)));
+        }
+        let rows = (0..n)
+            .map(|_| Ok([next()?.1, next()?.1, next()?.1]))
+            .collect::<Result<Vec<[f32; 3]>, LutError>>()?;
+        if let Some(value) = values.next() {
+            return Err(LutError::at(value?.0, "unexpected data after 1D table"));
+        }
+        for (c, curve) in curves.iter_mut().enumerate() {
+            for v in curve.iter_mut() {
+                let x = v.clamp(0.0, 1.0) * (n - 1) as f32;
+                let i = (x as usize).min(n - 2);
+                let t = x - i as f32;
+                *v = rows[i][c] * (1.0 - t) + rows[i + 1][c] * t;
+            }
+        }
+        return Ok(Lut {
+            title: None,
+            shaper: Some(LUT1DContext::new(curves, min, max)),
+            lut3d: None,
+        });
+    }
+    let shaper = Some(LUT1DContext::new(curves, min, max));
+
+    let (line, r) = next()?;
+    let sizes = [r, next()?.1, next()?.1].map(|v| v as usize);
+    let size = sizes[0];
+    if sizes.iter().any(|&s| s != size) || !(2..=MAX_LEVEL).contains(&size) {
+        return Err(LutError::at(line, format!("invalid LUT size {:?}", sizes)));
+    }
+    let rows = (0..size.pow(3))
+        .map(|_| Ok([next()?.1, next()?.1, next()?.1]))
+        .collect::<Result<Vec<[f32; 3]>, LutError>>()?;
+    if let Some(value) = values.next() {
+        return Err(LutError::at(value?.0, "unexpected data after 3D table"));
+    }
+    let lut3d = LUT3DContext::from_fn(size, |i, j, k| rows[(k * size + j) * size + i]);
+    Ok(Lut {
+        title: None,
+        shaper,
+        lut3d: Some(lut3d),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::LutInterpolation;
+
+    /// 对单个 16 位像素应用 lut
+    fn apply(lut: &Lut, rgb: [u16; 3]) -> [u16; 3] {
+        let out = lut.apply(&rgb, 1, 3, LutInterpolation::Tetrahedral, 1.0);
+        [out[0], out[1], out[2]]
+    }
+
+    /// 交换红蓝通道的 2 级 3D LUT 数据，红色变化最快
+    const SWAP_RB: &str = "0 0 0\n0 0 1\n0 1 0\n0 1 1\n1 0 0\n1 0 1\n1 1 0\n1 1 1\n";
+
+    #[test]
+    fn cube_3d() {
+        let text = format!("TITLE \"swap\"\nLUT_3D_SIZE 2\n{}", SWAP_RB);
+        let lut = LutLoader::parse(text.as_bytes(), LutFormat::Cube).unwrap();
+        assert_eq!(lut.title.as_deref(), Some("swap"));
+        assert_eq!(apply(&lut, [65535, 0, 0]), [0, 0, 65535]);
+        assert_eq!(apply(&lut, [0, 32768, 0]), [0, 32768, 0]);
+    }
+
+    #[test]
+    fn cube_1d() {
+        let text = "LUT_1D_SIZE 2\n1 1 1\n0 0 0\n";
+        let lut = LutLoader::parse(text.as_bytes(), LutFormat::Cube).unwrap();
+        assert_eq!(apply(&lut, [0, 65535, 16384]), [65535, 0, 49151]);
+    }
+
+    #[test]
+    fn three_dl() {
+        // 交换红蓝通道，蓝色变化最快，输出位深由最大值推断为 10 位
+        let text = "0 1023\n\
+                    0 0 0\n1023 0 0\n0 1023 0\n1023 1023 0\n\
+                    0 0 1023\n1023 0 1023\n0 1023 1023\n1023 1023 1023\n";
+        let lut = LutLoader::parse(text.as_bytes(), LutFormat::ThreeDl).unwrap();
+        assert_eq!(apply(&lut, [65535, 0, 0]), [0, 0, 65535]);
+        assert_eq!(apply(&lut, [0, 0, 65535]), [65535, 0, 0]);
+    }
+
+    #[test]
+    fn hald() {
+        // level 2 的恒等 Hald CLUT，8x8 图像对应 4 级 3D LUT
+        let img = image::RgbImage::from_fn(8, 8, |x, y| {
+            let n = y * 8 + x;
+            image::Rgb([n % 4, n / 4 % 4, n / 16].map(|v| (v * 85) as u8))
+        });
+        let mut png = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut png, image::ImageFormat::Png).unwrap();
+        assert_eq!(LutFormat::detect(png.get_ref()), Some(LutFormat::Hald));
+        let lut = LutLoader::parse(png.get_ref(), LutFormat::Hald).unwrap();
+        assert_eq!(apply(&lut, [65535, 21845, 0]), [65535, 21845, 0]);
+    }
+
+    #[test]
+    fn hald_level_9() {
+        // level 9 的恒等 Hald CLUT，729x729 图像对应 81 级 3D LUT，降采样为 MAX_LEVEL 级
+        let img = image::ImageBuffer::<image::Rgb<u16>, _>::from_fn(729, 729, |x, y| {
+            let n = y * 729 + x;
+            image::Rgb([n % 81, n / 81 % 81, n / 6561].map(|v| (v as f32 * 65535.0 / 80.0).round() as u16))
+        });
+        let mut png = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut png, image::ImageFormat::Png).unwrap();
+        let lut = LutLoader::parse(png.get_ref(), LutFormat::Hald).unwrap();
+        let out = apply(&lut, [65535, 40000, 1234]);
+        for (out, expected) in out.into_iter().zip([65535, 40000, 1234]) {
+            assert!(out.abs_diff(expected) <= 2, "{} != {}", out, expected);
+        }
+    }
+
+    #[test]
+    fn csp_3d() {
+        let text = format!("CSPLUTV100\n3D\n\n2\n0 1\n0 1\n2\n0 1\n0 1\n2\n0 1\n0 1\n\n2 2 2\n{}", SWAP_RB);
+        let lut = LutLoader::parse(text.as_bytes(), LutFormat::Csp).unwrap();
+        assert_eq!(apply(&lut, [65535, 0, 0]), [0, 0, 65535]);
+    }
+
+    #[test]
+    fn csp_1d() {
+        // pre-LUT 为恒等，1D 表将各通道反相
+        let text = "CSPLUTV100\n1D\n\n2\n0 1\n0 1\n2\n0 1\n0 1\n2\n0 1\n0 1\n\n3\n1 1 1\n0.5 0.5 0.5\n0 0 0\n";
+        let lut = LutLoader::parse(text.as_bytes(), LutFormat::Csp).unwrap();
+        assert_eq!(apply(&lut, [0, 65535, 16384]), [65535, 0, 49151]);
+    }
+
+    #[test]
+    fn csp_1d_trailing_data() {
+        let text = "CSPLUTV100\n1D\n2\n0 1\n0 1\n2\n0 1\n0 1\n2\n0 1\n0 1\n2\n1 1 1\n0 0 0\n0.5\n";
+        let err = LutLoader::parse(text.as_bytes(), LutFormat::Csp).unwrap_err();
+        assert!(matches!(err, LutError::Invalid { line: Some(15), .. }), "{}", err);
+    }
+
+    /// 解析失败时返回出错的行号
+    fn cube_error_line(text: &str) -> Option<usize> {
+        match LutLoader::parse(text.as_bytes(), LutFormat::Cube) {
+            Err(LutError::Invalid { line, .. }) => line,
+            other => panic!("expected invalid LUT, got {:?}", other.map(|lut| lut.title)),
+        }
+    }
+
+    #[test]
+    fn cube_rejects_duplicate_keyword() {
+        let text = format!("LUT_3D_SIZE 2\n# comment\nLUT_3D_SIZE 2\n{}", SWAP_RB);
+        assert_eq!(cube_error_line(&text), Some(3));
+    }
+
+    #[test]
+    fn cube_rejects_keyword_after_data() {
+        let text = format!("LUT_3D_SIZE 2\n{}TITLE \"late\"\n", SWAP_RB);
+        assert_eq!(cube_error_line(&text), Some(10));
+    }
+
+    #[test]
+    fn cube_rejects_invalid_lines() {
+        assert_eq!(cube_error_line("FOO 1\n"), Some(1));
+        assert_eq!(cube_error_line("TITLE unquoted\n"), Some(1));
+        assert_eq!(cube_error_line("LUT_3D_SIZE 1\n"), Some(1));
+        assert_eq!(cube_error_line("LUT_3D_SIZE 2\n0 0 0\n0 0 nan\n"), Some(3));
+        assert_eq!(cube_error_line("LUT_3D_SIZE 2\n0 0\n"), Some(2));
+        assert_eq!(cube_error_line("DOMAIN_MIN 0 0 0\nDOMAIN_MAX 1 0 1\nLUT_1D_SIZE 2\n0 0 0\n1 1 1\n"), Some(2));
+    }
+
+    #[test]
+    fn cube_rejects_wrong_row_count() {
+        let text = format!("LUT_3D_SIZE 2\n{}0 0 0\n", SWAP_RB);
+        assert_eq!(cube_error_line(&text), None);
+        assert_eq!(cube_error_line("LUT_3D_SIZE 2\n0 0 0\n"), None);
+        assert_eq!(cube_error_line("TITLE \"empty\"\n"), None);
+    }
+
+    #[test]
+    fn cube_accepts_video_range_and_domain() {
+        let text = format!("LUT_IN_VIDEO_RANGE\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 2 2 2\nLUT_3D_SIZE 2\n{}", SWAP_RB);
+        let lut = LutLoader::parse(text.as_bytes(), LutFormat::Cube).unwrap();
+        // 输入范围为 0-2，1.0 位于网格中点
+        assert_eq!(apply(&lut, [65535, 0, 0]), [0, 0, 32768]);
+    }
+
+    #[test]
+    fn detect() {
+        assert_eq!(LutFormat::detect(b"CSPLUTV100\n3D\n"), Some(LutFormat::Csp));
+        assert_eq!(LutFormat::detect(b"LUT_3D_SIZE 2\n"), Some(LutFormat::Cube));
+        assert_eq!(LutFormat::detect(b"0 512 1023\n"), Some(LutFormat::ThreeDl));
+        assert_eq!(LutFormat::from_extension("TIFF"), Some(LutFormat::Hald));
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use chrono::FixedOffset;
+use exif::{Context, Exif, Field, In, Reader, Tag, Value};
+use libraw_rs_vendor::libraw_gps_info_t;
+use serde::{Deserialize, Serialize};
+
+/// 从 TIFF 主 IFD 中保留的字段，其余多为描述 Raw 数据结构的字段
+const TIFF_TAGS: &[Tag] = &[
+    Tag::Make,
+    Tag::Model,
+    Tag::Orientation,
+    Tag::DateTime,
+    Tag::ImageDescription,
+    Tag::Software,
+    Tag::Artist,
+    Tag::Copyright,
+];
+
+/// 厂商私有或只适用于原始数据的 Exif 字段，不写入输出图像
+const EXIF_SKIP_TAGS: &[Tag] = &[
+    Tag::MakerNote,
+    Tag::PixelXDimension,
+    Tag::PixelYDimension,
+    Tag::ComponentsConfiguration,
+    Tag::CompressedBitsPerPixel,
+    Tag::ColorSpace,
+];
+
+/// 解析 exif 时依次尝试读取的文件开头长度，exif 一般位于文件开头
+const HEAD_SIZES: [u64; 2] = [1 << 20, 16 << 20];
+
+/// Raw 文件中与厂商无关的 exif 字段，用于原样写入输出图像
+#[derive(Debug, Clone, Default)]
+pub struct SourceExif {
+    fields: Vec<Field>,
+}
+
+impl SourceExif {
+    /// 解析 Raw 文件数据中的 exif，无法识别的格式返回空
+    pub(crate) fn parse(data: &[u8]) -> Self {
+        let mut fields: Vec<Field> = read_exif(data)
+            .map(|exif| exif.fields().filter_map(keep_field).collect())
+            .unwrap_or_default();
+        // 同一字段可能重复出现，写入时只能保留一个
+        let mut seen = HashSet::new();
+        fields.retain(|field| seen.insert(field.tag));
+        SourceExif { fields }
+    }
+
+    /// 只读取文件开头解析 exif，避免把整个 Raw 文件读入内存
+    pub(crate) fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        for limit in HEAD_SIZES {
+            let mut data = Vec::new();
+            file.seek(SeekFrom::Start(0))?;
+            (&mut file).take(limit).read_to_end(&mut data)?;
+            let exif = SourceExif::parse(&data);
+            if !exif.is_empty() || (data.len() as u64) < limit {
+                return Ok(exif);
+            }
+        }
+        Ok(SourceExif::default())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub(crate) fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// 移除 GPS 信息
+    pub fn strip_gps(&mut self) {
+        self.fields.retain(|field| field.tag.context() != Context::Gps);
+    }
+
+    /// 替换输出图像的方向标签
+    pub(crate) fn set_orientation(&mut self, orientation: u16) {
+        self.fields.retain(|field| field.tag != Tag::Orientation);
+        self.fields.push(Field {
+            tag: Tag::Orientation,
+            ifd_num: In::PRIMARY,
+            value: Value::Short(vec![orientation]),
+        });
+    }
+
+    fn get(&self, tag: Tag) -> Option<&Field> {
+        self.fields.iter().find(|field| field.tag == tag)
+    }
+
+    /// 曝光补偿，单位 EV
+    pub(crate) fn exposure_bias(&self) -> Option<f32> {
+        match &self.get(Tag::ExposureBiasValue)?.value {
+            Value::SRational(v) => v.first().map(|r| r.to_f32()),
+            _ => None,
+        }
+    }
+
+    /// 拍摄时间的时区偏移，格式如 "+08:00"
+    pub(crate) fn offset_time(&self) -> Option<FixedOffset> {
+        let field = self
+            .get(Tag::OffsetTimeOriginal)
+            .or_else(|| self.get(Tag::OffsetTime))?;
+        match &field.value {
+            Value::Ascii(v) => std::str::from_utf8(v.first()?).ok()?.trim().parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// GPS 坐标，南纬和西经为负数
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GpsInfo {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// 海拔，单位 m，海平面以下为负数
+    pub altitude: f32,
+}
+
+impl GpsInfo {
+    pub(crate) fn from_libraw(gps: &libraw_gps_info_t) -> Option<Self> {
+        if gps.gpsparsed == 0 {
+            return None;
+        }
+        let degrees = |v: [f32; 3]| v[0] as f64 + v[1] as f64 / 60.0 + v[2] as f64 / 3600.0;
+        let mut latitude = degrees(gps.latitude);
+        if gps.latref as u8 == b'S' {
+            latitude = -latitude;
+        }
+        let mut longitude = degrees(gps.longitude);
+        if gps.longref as u8 == b'W' {
+            longitude = -longitude;
+        }
+        let altitude = if gps.altref == 0 { gps.altitude } else { -gps.altitude };
+        Some(GpsInfo {
+            latitude,
+            longitude,
+            altitude,
+        })
+    }
+}
+
+/// 将 libraw 的 flip 转换为 exif 方向值
+pub(crate) fn orientation_from_flip(flip: i32) -> u16 {
+    match flip {
+        3 => 3,
+        5 => 8,
+        6 => 6,
+        _ => 1,
+    }
+}
+
+fn read_exif(data: &[u8]) -> Option<Exif> {
+    let reader = Reader::new();
+    match data.get(..4)? {
+        // 松下 RW2 和奥林巴斯 ORF 仅修改了 TIFF 头的魔数
+        b"IIU\0" | b"IIRO" | b"IIRS" | b"MMOR" => {
+            let mut data = data.to_vec();
+            let magic: [u8; 2] = if data[0] == b'I' { [42, 0] } else { [0, 42] };
+            data[2..4].copy_from_slice(&magic);
+            reader.read_raw(data).ok()
+        }
+        // 富士 RAF 在文件头指定的位置嵌入了带 exif 的 JPEG 预览
+        b"FUJI" => {
+            let offset = u32::from_be_bytes(data.get(84..88)?.try_into().ok()?) as usize;
+            let len = u32::from_be_bytes(data.get(88..92)?.try_into().ok()?) as usize;
+            // 只读取了文件开头时预览可能不完整，exif 位于 JPEG 开头
+            let end = offset.checked_add(len)?.min(data.len());
+            let jpeg = data.get(offset..end)?;
+            reader.read_from_container(&mut Cursor::new(jpeg)).ok()
+        }
+        // DNG、NEF、CR2、ARW 等标准 TIFF 结构
+        _ => reader.read_from_container(&mut Cursor::new(data)).ok(),
+    }
+}
+
+fn keep_field(field: &Field) -> Option<Field> {
+    if field.ifd_num != In::PRIMARY {
+        return None;
+    }
+    let keep = match field.tag.context() {
+        Context::Tiff => TIFF_TAGS.contains(&field.tag),
+        Context::Exif => field.tag.description().is_some() && !EXIF_SKIP_TAGS.contains(&field.tag),
+        Context::Gps => field.tag.description().is_some(),
+        _ => false,
+    };
+    if !keep {
+        return None;
+    }
+    let mut field = field.clone();
+    if field.tag == Tag::Orientation {
+        // 默认按方向旋转像素，不旋转时由 set_orientation 替换
+        field.value = Value::Short(vec![1]);
+    }
+    Some(field)
+}
@@ -0,0 +1,100 @@
+use rayon::prelude::*;
+
+use crate::options::NoiseReduction;
+
+/// BT.709 YCbCr 系数
+const KR: f32 = 0.2126;
+const KB: f32 = 0.0722;
+const KG: f32 = 1.0 - KR - KB;
+
+/// 去马赛克之后分别对亮度和色度做双边滤波
+///
+/// 色度噪点空间尺度更大，使用更大的半径，对细节的影响也更小
+pub(crate) fn reduce_noise(data: &mut [u16], width: usize, height: usize, nr: &NoiseReduction) {
+    if (nr.luminance <= 0.0 && nr.chroma <= 0.0) || width == 0 || height == 0 {
+        return;
+    }
+    let (mut luma, mut cb, mut cr) = to_ycbcr(data);
+    if nr.luminance > 0.0 {
+        let strength = nr.luminance.min(100.0) / 100.0;
+        luma = bilateral(&luma, width, height, 2, 1.5, 0.002 + 0.05 * strength);
+    }
+    if nr.chroma > 0.0 {
+        let strength = nr.chroma.min(100.0) / 100.0;
+        let sigma_r = 0.005 + 0.1 * strength;
+        cb = bilateral(&cb, width, height, 3, 2.5, sigma_r);
+        cr = bilateral(&cr, width, height, 3, 2.5, sigma_r);
+    }
+    from_ycbcr(data, &luma, &cb, &cr);
+}
+
+fn to_ycbcr(data: &[u16]) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+    let pixels: Vec<(f32, f32, f32)> = data
+        .par_chunks_exact(3)
+        .map(|p| {
+            let [r, g, b] = [p[0], p[1], p[2]].map(|v| v as f32 / 65535.0);
+            let y = KR * r + KG * g + KB * b;
+            (y, (b - y) / (2.0 * (1.0 - KB)), (r - y) / (2.0 * (1.0 - KR)))
+        })
+        .collect();
+    let mut luma = Vec::with_capacity(pixels.len());
+    let mut cb = Vec::with_capacity(pixels.len());
+    let mut cr = Vec::with_capacity(pixels.len());
+    for (y, b, r) in pixels {
+        luma.push(y);
+        cb.push(b);
+        cr.push(r);
+    }
+    (luma, cb, cr)
+}
+
+fn from_ycbcr(data: &mut [u16], luma: &[f32], cb: &[f32], cr: &[f32]) {
+    data.par_chunks_exact_mut(3).enumerate().for_each(|(i, p)| {
+        let y = luma[i];
+        let r = y + 2.0 * (1.0 - KR) * cr[i];
+        let b = y + 2.0 * (1.0 - KB) * cb[i];
+        let g = (y - KR * r - KB * b) / KG;
+        for (v, c) in p.iter_mut().zip([r, g, b]) {
+            *v = (c.clamp(0.0, 1.0) * 65535.0).round() as u16;
+        }
+    });
+}
+
+/// 单通道双边滤波，边缘像素按最近的像素延伸
+fn bilateral(
+    src: &[f32],
+    width: usize,
+    height: usize,
+    radius: isize,
+    sigma_s: f32,
+    sigma_r: f32,
+) -> Vec<f32> {
+    let size = (2 * radius + 1) as usize;
+    let spatial: Vec<f32> = (-radius..=radius)
+        .flat_map(|dy| (-radius..=radius).map(move |dx| (dx, dy)))
+        .map(|(dx, dy)| (-((dx * dx + dy * dy) as f32) / (2.0 * sigma_s * sigma_s)).exp())
+        .collect();
+    let range_coeff = -1.0 / (2.0 * sigma_r * sigma_r);
+    let (w, h) = (width as isize, height as isize);
+
+    let mut dst = vec![0.0; src.len()];
+    dst.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+        for (x, out) in row.iter_mut().enumerate() {
+            let center = src[y * width + x];
+            let (mut sum, mut weight_sum) = (0.0, 0.0);
+            for (j, dy) in (-radius..=radius).enumerate() {
+                let yy = (y as isize + dy).clamp(0, h - 1) as usize;
+                for (i, dx) in (-radius..=radius).enumerate() {
+                    let xx = (x as isize + dx).clamp(0, w - 1) as usize;
+                    let v = src[yy * width + xx];
+                    let d = v - center;
+                    let weight = spatial[j * size + i] * (d * d * range_coeff).exp();
+                    sum += v * weight;
+                    weight_sum += weight;
+                }
+            }
+            *out = sum / weight_sum;
+        }
+    });
+    dst
+}
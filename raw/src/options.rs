@@ -0,0 +1,979 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::auto_exposure::EV_RANGE;
+use crate::error::RawError;
+use crate::white_balance::KELVIN_RANGE;
+
+/// 白平衡模式
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WhiteBalance {
+    /// 自动白平衡
+    Auto,
+    /// 相机白平衡
+    #[default]
+    Camera,
+    /// 色温（K）和色调，色调范围 -150 到 150，正值偏品红
+    Temperature { kelvin: f32, tint: f32 },
+    /// 直接指定 RGBG 通道倍率，范围 0-16
+    Multipliers([f32; 4]),
+    /// 以图像中的中性灰点计算白平衡，坐标为相对显示图像宽高的比例
+    GreyPoint { x: f32, y: f32 },
+}
+
+/// 曝光补偿模式
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Exposure {
+    /// 根据亮度直方图自动计算
+    #[default]
+    Auto,
+    /// 手动曝光补偿，单位 EV，范围 -2 到 3
+    Manual(f32),
+}
+
+/// 自动曝光的测光模式
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metering {
+    /// 全画面平均
+    Average,
+    /// 中央重点，权重随到中心的距离衰减
+    #[default]
+    CenterWeighted,
+}
+
+impl Metering {
+    /// 根据名称识别测光方式，不区分大小写
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "average" => Some(Metering::Average),
+            "center" | "center_weighted" => Some(Metering::CenterWeighted),
+            _ => None,
+        }
+    }
+}
+
+/// 降噪模式
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Denoise {
+    /// 根据 ISO 自动计算小波降噪阈值
+    #[default]
+    Auto,
+    /// 手动指定小波降噪阈值，范围 0-20000
+    Manual(f32),
+}
+
+/// 降噪阶段的参数，亮度和色度降噪在去马赛克之后进行
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseReduction {
+    /// 亮度降噪强度，范围 0-100
+    pub luminance: f32,
+    /// 色度降噪强度，范围 0-100
+    pub chroma: f32,
+    /// libraw 的 FBDD 降噪，0 关闭，1 仅亮度，2 亮度和色度，只对拜耳阵列生效
+    pub fbdd: u8,
+    /// libraw 去马赛克后 3x3 中值滤波的次数，范围 0-10
+    pub median_passes: u8,
+}
+
+/// 高光恢复模式
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Highlight {
+    /// 裁切为白色
+    #[default]
+    Clip,
+    /// 不裁切，保留溢出的通道值
+    Unclip,
+    /// 混合裁切与未裁切的值，得到过渡自然的白色
+    Blend,
+    /// 重建高光，级别范围 3-9，越高越偏向色彩而非亮度
+    Rebuild(u8),
+}
+
+impl Highlight {
+    /// 根据名称识别高光模式，不区分大小写，重建模式写作 rebuild3 到 rebuild9
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "clip" => Some(Highlight::Clip),
+            "unclip" => Some(Highlight::Unclip),
+            "blend" => Some(Highlight::Blend),
+            other => match other.strip_prefix("rebuild")?.parse::<u8>().ok()? {
+                level @ 3..=9 => Some(Highlight::Rebuild(level)),
+                _ => None,
+            },
+        }
+    }
+
+    /// libraw 的 highlight 取值
+    pub fn mode(&self) -> i32 {
+        match self {
+            Highlight::Clip => 0,
+            Highlight::Unclip => 1,
+            Highlight::Blend => 2,
+            Highlight::Rebuild(level) => (*level).clamp(3, 9) as i32,
+        }
+    }
+}
+
+/// 去马赛克插值算法，半尺寸输出时不进行插值，此参数不起作用
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Demosaic {
+    /// 双线性插值，速度最快
+    Linear,
+    Vng,
+    Ppg,
+    #[default]
+    Ahd,
+    Dcb,
+    Dht,
+    /// 改进的 AHD
+    Aahd,
+}
+
+impl Demosaic {
+    /// 根据名称识别算法，不区分大小写
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "linear" => Some(Demosaic::Linear),
+            "vng" => Some(Demosaic::Vng),
+            "ppg" => Some(Demosaic::Ppg),
+            "ahd" => Some(Demosaic::Ahd),
+            "dcb" => Some(Demosaic::Dcb),
+            "dht" => Some(Demosaic::Dht),
+            "aahd" => Some(Demosaic::Aahd),
+            _ => None,
+        }
+    }
+
+    /// libraw 的 user_qual 取值
+    pub fn user_qual(&self) -> i32 {
+        match self {
+            Demosaic::Linear => 0,
+            Demosaic::Vng => 1,
+            Demosaic::Ppg => 2,
+            Demosaic::Ahd => 3,
+            Demosaic::Dcb => 4,
+            Demosaic::Dht => 11,
+            Demosaic::Aahd => 12,
+        }
+    }
+}
+
+/// lut 插值方式
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LutInterpolation {
+    /// 取最近的网格点，速度最快，低精度 lut 上会出现色阶断层
+    Nearest,
+    Trilinear,
+    #[default]
+    Tetrahedral,
+}
+
+impl LutInterpolation {
+    /// 根据名称识别插值方式，不区分大小写
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "nearest" => Some(LutInterpolation::Nearest),
+            "trilinear" => Some(LutInterpolation::Trilinear),
+            "tetrahedral" => Some(LutInterpolation::Tetrahedral),
+            _ => None,
+        }
+    }
+}
+
+/// 输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    #[default]
+    Jpeg,
+    Webp,
+    Png,
+    Tiff,
+    Avif,
+    Jxl,
+}
+
+impl OutputFormat {
+    /// 根据扩展名识别输出格式，不区分大小写
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "jpg" | "jpeg" => Some(OutputFormat::Jpeg),
+            "webp" => Some(OutputFormat::Webp),
+            "png" => Some(OutputFormat::Png),
+            "tif" | "tiff" => Some(OutputFormat::Tiff),
+            "avif" => Some(OutputFormat::Avif),
+            "jxl" => Some(OutputFormat::Jxl),
+            _ => None,
+        }
+    }
+
+    /// 根据文件路径的扩展名识别输出格式，无法识别时返回错误
+    pub fn from_path(path: &str) -> Result<Self, RawError> {
+        let ext = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+        OutputFormat::from_extension(ext).ok_or_else(|| RawError::UnknownFormat(ext.to_string()))
+    }
+
+    /// 检查输出文件的扩展名，没有扩展名时不检查，无法识别或与当前格式不一致时返回错误
+    pub fn check_path(&self, path: &str) -> Result<(), RawError> {
+        if Path::new(path).extension().is_none_or(|ext| ext.is_empty()) {
+            return Ok(());
+        }
+        match OutputFormat::from_path(path)? {
+            format if format == *self => Ok(()),
+            _ => Err(RawError::FormatMismatch {
+                path: path.to_string(),
+                format: *self,
+            }),
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Png => "png",
+            OutputFormat::Tiff => "tiff",
+            OutputFormat::Avif => "avif",
+            OutputFormat::Jxl => "jxl",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::Webp => "image/webp",
+            OutputFormat::Png => "image/png",
+            OutputFormat::Tiff => "image/tiff",
+            OutputFormat::Avif => "image/avif",
+            OutputFormat::Jxl => "image/jxl",
+        }
+    }
+
+    /// 是否支持 16 位输出
+    pub fn supports_16bit(&self) -> bool {
+        matches!(self, OutputFormat::Png | OutputFormat::Tiff)
+    }
+}
+
+/// 图像方向的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Orientation {
+    /// 按 Raw 文件记录的方向旋转像素
+    #[default]
+    Rotate,
+    /// 保持传感器方向，在 exif 中写入方向标签，不嵌入 exif 时仍旋转像素
+    Tag,
+}
+
+/// 输出色彩空间，输出图像会嵌入对应的 ICC 配置文件
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorSpace {
+    #[default]
+    Srgb,
+    AdobeRgb,
+    DisplayP3,
+    ProPhoto,
+    /// 线性 Rec.2020，建议配合 16 位输出使用
+    Rec2020Linear,
+}
+
+impl ColorSpace {
+    /// 根据名称识别色彩空间，不区分大小写
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "srgb" => Some(ColorSpace::Srgb),
+            "adobe" | "adobe_rgb" => Some(ColorSpace::AdobeRgb),
+            "p3" | "display_p3" => Some(ColorSpace::DisplayP3),
+            "prophoto" | "pro_photo" => Some(ColorSpace::ProPhoto),
+            "rec2020" | "rec2020_linear" => Some(ColorSpace::Rec2020Linear),
+            _ => None,
+        }
+    }
+
+    /// libraw 的 output_color 取值
+    pub fn output_color(&self) -> i32 {
+        match self {
+            ColorSpace::Srgb => 1,
+            ColorSpace::AdobeRgb => 2,
+            ColorSpace::ProPhoto => 4,
+            ColorSpace::DisplayP3 => 7,
+            ColorSpace::Rec2020Linear => 8,
+        }
+    }
+
+    /// libraw 的 gamm 参数，分别为幂指数和线性段斜率，与配置文件中的曲线一致
+    pub fn gamma(&self) -> (f64, f64) {
+        match self {
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => (1.0 / 2.4, 12.92),
+            ColorSpace::AdobeRgb => (256.0 / 563.0, 0.0),
+            ColorSpace::ProPhoto => (1.0 / 1.8, 0.0),
+            ColorSpace::Rec2020Linear => (1.0, 1.0),
+        }
+    }
+}
+
+/// 输出位深，JPEG、WebP 和 AVIF 始终输出 8 位
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BitDepth {
+    #[default]
+    Eight,
+    Sixteen,
+}
+
+/// 色调调整，除色调曲线外取值范围均为 -100 到 100，0 表示不调整
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Adjustments {
+    pub contrast: f32,
+    pub highlights: f32,
+    pub shadows: f32,
+    pub whites: f32,
+    pub blacks: f32,
+    pub saturation: f32,
+    pub vibrance: f32,
+    /// 色调曲线控制点（输入，输出），范围 0-1，最多 32 个，少于两个点时不使用
+    pub tone_curve: Vec<(f32, f32)>,
+}
+
+impl Adjustments {
+    /// 所有参数均不改变图像
+    pub fn is_identity(&self) -> bool {
+        [
+            self.contrast,
+            self.highlights,
+            self.shadows,
+            self.whites,
+            self.blacks,
+            self.saturation,
+            self.vibrance,
+        ]
+        .iter()
+        .all(|&v| v == 0.0)
+            && self.tone_curve.len() < 2
+    }
+
+    /// 解析 "x:y,x:y" 格式的色调曲线控制点，格式错误时返回空
+    pub fn parse_tone_curve(text: &str) -> Option<Vec<(f32, f32)>> {
+        text.split(',')
+            .filter(|p| !p.trim().is_empty())
+            .map(|p| {
+                let (x, y) = p.split_once(':')?;
+                Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+            })
+            .collect()
+    }
+}
+
+/// 色调映射算子
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMapOperator {
+    /// 不做色调映射
+    #[default]
+    None,
+    /// 幂函数曲线，shift 为 gamma 值
+    Gamma,
+    /// 暗部线性提升、高光平滑压缩
+    Linear,
+    /// Uncharted 2 filmic 曲线
+    Filmic,
+    Aces,
+    Reinhard,
+}
+
+impl ToneMapOperator {
+    /// 根据名称识别算子，不区分大小写
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "none" => Some(ToneMapOperator::None),
+            "gamma" => Some(ToneMapOperator::Gamma),
+            "linear" => Some(ToneMapOperator::Linear),
+            "filmic" => Some(ToneMapOperator::Filmic),
+            "aces" => Some(ToneMapOperator::Aces),
+            "reinhard" => Some(ToneMapOperator::Reinhard),
+            _ => None,
+        }
+    }
+}
+
+/// 色调映射参数
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    /// 曝光倍率，范围 0.1-8，gamma 算子中为 gamma 值
+    pub shift: f32,
+    /// linear 算子保留高光的程度，范围 0-1
+    pub smooth: f32,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping {
+            operator: ToneMapOperator::default(),
+            shift: 1.0,
+            smooth: 0.9,
+        }
+    }
+}
+
+/// 镜头校正参数，手动参数在配置文件校正的基础上叠加
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LensCorrection {
+    /// 根据 exif 中的镜头型号查找配置文件，校正暗角、畸变和横向色差
+    pub profile: bool,
+    /// lensfun 格式的 XML 数据库文件或目录，由服务端设置，不从请求参数中读取
+    #[serde(skip)]
+    pub database: String,
+    /// 手动暗角补偿，范围 -100 到 100，正值提亮边角
+    pub vignetting: f32,
+    /// 手动畸变校正，范围 -100 到 100，正值校正桶形畸变
+    pub distortion: f32,
+    /// libraw 色差校正中红色通道的缩放倍率，范围 0.99-1.01，1 表示不校正
+    pub ca_red: f32,
+    /// libraw 色差校正中蓝色通道的缩放倍率，范围 0.99-1.01，1 表示不校正
+    pub ca_blue: f32,
+}
+
+impl Default for LensCorrection {
+    fn default() -> Self {
+        LensCorrection {
+            profile: false,
+            database: "lensfun".to_string(),
+            vignetting: 0.0,
+            distortion: 0.0,
+            ca_red: 1.0,
+            ca_blue: 1.0,
+        }
+    }
+}
+
+/// 锐化参数，在所有缩放之后、添加相框之前应用
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sharpening {
+    /// USM 锐化强度（%），范围 0-500，0 表示不锐化
+    pub amount: f32,
+    /// USM 模糊半径（像素），范围 0.3-5
+    pub radius: f32,
+    /// 亮度差低于该值（8 位色阶）的像素不锐化，范围 0-255，用于避免放大平滑区域的噪点
+    pub threshold: f32,
+    /// 清晰度，范围 -100 到 100，调整中间调的大半径局部对比度
+    pub clarity: f32,
+}
+
+impl Default for Sharpening {
+    fn default() -> Self {
+        Sharpening {
+            amount: 0.0,
+            radius: 1.0,
+            threshold: 0.0,
+            clarity: 0.0,
+        }
+    }
+}
+
+/// 裁切比例预设，横竖方向跟随裁切区域
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AspectRatio {
+    /// 不限制比例
+    #[default]
+    Free,
+    /// 与旋转后的原图比例相同
+    Original,
+    Square,
+    Ratio3x2,
+    Ratio4x3,
+    Ratio5x4,
+    Ratio16x9,
+}
+
+impl AspectRatio {
+    /// 根据名称获取比例预设，如 free、original、1:1、3:2
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "free" => Some(AspectRatio::Free),
+            "original" => Some(AspectRatio::Original),
+            "1:1" | "square" => Some(AspectRatio::Square),
+            "3:2" => Some(AspectRatio::Ratio3x2),
+            "4:3" => Some(AspectRatio::Ratio4x3),
+            "5:4" => Some(AspectRatio::Ratio5x4),
+            "16:9" => Some(AspectRatio::Ratio16x9),
+            _ => None,
+        }
+    }
+
+    /// 宽高比，original 使用传入的原图宽高比
+    pub(crate) fn ratio(&self, original: f32) -> Option<f32> {
+        match self {
+            AspectRatio::Free => None,
+            AspectRatio::Original => Some(original),
+            AspectRatio::Square => Some(1.0),
+            AspectRatio::Ratio3x2 => Some(3.0 / 2.0),
+            AspectRatio::Ratio4x3 => Some(4.0 / 3.0),
+            AspectRatio::Ratio5x4 => Some(5.0 / 4.0),
+            AspectRatio::Ratio16x9 => Some(16.0 / 9.0),
+        }
+    }
+}
+
+/// 裁切区域，坐标和尺寸为相对旋转、拉直后图像宽高的比例
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CropRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl CropRect {
+    /// 解析 "x,y,宽,高" 格式的裁切区域
+    pub fn parse(text: &str) -> Option<Self> {
+        let values: Vec<f32> = text
+            .split(',')
+            .map(|v| v.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        match values[..] {
+            [x, y, width, height] => Some(CropRect {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// 几何变换参数，按 90° 旋转、拉直、裁切的顺序应用
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Geometry {
+    /// 顺时针旋转 90° 的次数，范围 0-3
+    pub rotate: u8,
+    /// 拉直角度，范围 -45 到 45，正值顺时针
+    pub straighten: f32,
+    /// 拉直后自动裁掉边角的空白区域
+    pub auto_crop: bool,
+    /// 裁切区域，为空时使用整个画面
+    pub crop: Option<CropRect>,
+    pub aspect: AspectRatio,
+}
+
+impl Default for Geometry {
+    fn default() -> Self {
+        Geometry {
+            rotate: 0,
+            straighten: 0.0,
+            auto_crop: true,
+            crop: None,
+            aspect: AspectRatio::Free,
+        }
+    }
+}
+
+impl Geometry {
+    /// 不改变图像
+    pub fn is_identity(&self) -> bool {
+        self.rotate.is_multiple_of(4)
+            && self.straighten == 0.0
+            && self.crop.is_none()
+            && self.aspect == AspectRatio::Free
+    }
+}
+
+/// 相框参数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrameOptions {
+    /// 相框文字使用的字体文件
+    pub font_file: String,
+}
+
+/// Raw 转换参数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessOptions {
+    pub white_balance: WhiteBalance,
+    pub exposure: Exposure,
+    pub metering: Metering,
+    pub denoise: Denoise,
+    pub noise_reduction: NoiseReduction,
+    pub lens_correction: LensCorrection,
+    pub highlight: Highlight,
+    /// 提升曝光时保护高光的程度，范围 0-1
+    pub exp_preserve: f32,
+    /// 输出尺寸减半
+    pub half_size: bool,
+    pub demosaic: Demosaic,
+    pub format: OutputFormat,
+    pub bit_depth: BitDepth,
+    pub color_space: ColorSpace,
+    /// 去马赛克之后、色调调整之前应用的色调映射
+    pub tone_mapping: ToneMapping,
+    /// 去马赛克之后、3D LUT 之前应用的色调调整
+    pub adjustments: Adjustments,
+    pub sharpening: Sharpening,
+    /// 旋转、拉直和裁切，在镜头校正之后应用，为空时不变换
+    pub geometry: Option<Geometry>,
+    /// 输出质量，范围 1-100
+    pub quality: u8,
+    pub embed_exif: bool,
+    /// 输出时去除 GPS 信息
+    pub strip_gps: bool,
+    /// 添加相框时始终旋转像素
+    pub orientation: Orientation,
+    /// 添加相框，为空时不添加
+    pub frame: Option<FrameOptions>,
+    /// lut 文件路径，为空时不使用滤镜，由服务端根据 lut_id 设置，不从请求参数中读取
+    #[serde(skip)]
+    pub lut: Option<String>,
+    /// 服务端上传的 lut 编号
+    pub lut_id: Option<i32>,
+    /// lut 强度（%），范围 0-100，按比例与应用前的图像混合
+    pub lut_intensity: f32,
+    /// 在第一个 lut 之后叠加的 lut 文件路径，由服务端根据 second_lut_id 设置
+    #[serde(skip)]
+    pub second_lut: Option<String>,
+    /// 叠加的 lut 在服务端的编号
+    pub second_lut_id: Option<i32>,
+    /// 第二个 lut 的强度（%），范围 0-100
+    pub second_lut_intensity: f32,
+    /// lut 插值方式，两个 lut 共用
+    pub lut_interpolation: LutInterpolation,
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        ProcessOptions {
+            white_balance: WhiteBalance::default(),
+            exposure: Exposure::default(),
+            metering: Metering::default(),
+            denoise: Denoise::default(),
+            noise_reduction: NoiseReduction::default(),
+            lens_correction: LensCorrection::default(),
+            highlight: Highlight::default(),
+            exp_preserve: 0.8,
+            half_size: false,
+            demosaic: Demosaic::default(),
+            format: OutputFormat::default(),
+            bit_depth: BitDepth::default(),
+            color_space: ColorSpace::default(),
+            tone_mapping: ToneMapping::default(),
+            adjustments: Adjustments::default(),
+            sharpening: Sharpening::default(),
+            geometry: None,
+            quality: 90,
+            embed_exif: true,
+            strip_gps: false,
+            orientation: Orientation::default(),
+            frame: None,
+            lut: None,
+            lut_id: None,
+            lut_intensity: 100.0,
+            second_lut: None,
+            second_lut_id: None,
+            second_lut_intensity: 100.0,
+            lut_interpolation: LutInterpolation::default(),
+        }
+    }
+}
+
+impl ProcessOptions {
+    pub fn builder() -> ProcessOptionsBuilder {
+        ProcessOptionsBuilder::default()
+    }
+
+    /// 将参数限制在允许的范围内，NaN 按 0 处理
+    ///
+    /// 从请求或数据库反序列化的参数未经过 builder，使用前需要调用
+    pub fn sanitize(&mut self) {
+        match &mut self.white_balance {
+            WhiteBalance::Temperature { kelvin, tint } => {
+                *kelvin = clamp(*kelvin, KELVIN_RANGE.0, KELVIN_RANGE.1);
+                *tint = clamp(*tint, -150.0, 150.0);
+            }
+            WhiteBalance::Multipliers(mul) => {
+                for v in mul {
+                    *v = clamp(*v, 0.0, 16.0);
+                }
+            }
+            WhiteBalance::GreyPoint { x, y } => {
+                *x = clamp(*x, 0.0, 1.0);
+                *y = clamp(*y, 0.0, 1.0);
+            }
+            WhiteBalance::Auto | WhiteBalance::Camera => {}
+        }
+        if let Exposure::Manual(ev) = &mut self.exposure {
+            *ev = clamp(*ev, EV_RANGE.0, EV_RANGE.1);
+        }
+        if let Denoise::Manual(threshold) = &mut self.denoise {
+            *threshold = clamp(*threshold, 0.0, 20000.0);
+        }
+        if let Highlight::Rebuild(level) = &mut self.highlight {
+            *level = (*level).clamp(3, 9);
+        }
+        self.quality = self.quality.clamp(1, 100);
+        self.exp_preserve = clamp(self.exp_preserve, 0.0, 1.0);
+        self.lut_intensity = clamp(self.lut_intensity, 0.0, 100.0);
+        self.second_lut_intensity = clamp(self.second_lut_intensity, 0.0, 100.0);
+        let noise_reduction = &mut self.noise_reduction;
+        noise_reduction.luminance = clamp(noise_reduction.luminance, 0.0, 100.0);
+        noise_reduction.chroma = clamp(noise_reduction.chroma, 0.0, 100.0);
+        noise_reduction.fbdd = noise_reduction.fbdd.min(2);
+        noise_reduction.median_passes = noise_reduction.median_passes.min(10);
+        let lens_correction = &mut self.lens_correction;
+        lens_correction.vignetting = clamp(lens_correction.vignetting, -100.0, 100.0);
+        lens_correction.distortion = clamp(lens_correction.distortion, -100.0, 100.0);
+        lens_correction.ca_red = clamp(lens_correction.ca_red, 0.99, 1.01);
+        lens_correction.ca_blue = clamp(lens_correction.ca_blue, 0.99, 1.01);
+        let sharpening = &mut self.sharpening;
+        sharpening.amount = clamp(sharpening.amount, 0.0, 500.0);
+        sharpening.radius = clamp(sharpening.radius, 0.3, 5.0);
+        sharpening.threshold = clamp(sharpening.threshold, 0.0, 255.0);
+        sharpening.clarity = clamp(sharpening.clarity, -100.0, 100.0);
+        if let Some(geometry) = &mut self.geometry {
+            geometry.rotate %= 4;
+            geometry.straighten = clamp(geometry.straighten, -45.0, 45.0);
+            if let Some(crop) = &mut geometry.crop {
+                for v in [&mut crop.x, &mut crop.y, &mut crop.width, &mut crop.height] {
+                    *v = clamp(*v, 0.0, 1.0);
+                }
+            }
+        }
+        let tone_mapping = &mut self.tone_mapping;
+        tone_mapping.shift = clamp(tone_mapping.shift, 0.1, 8.0);
+        tone_mapping.smooth = clamp(tone_mapping.smooth, 0.0, 1.0);
+        let adjustments = &mut self.adjustments;
+        for v in [
+            &mut adjustments.contrast,
+            &mut adjustments.highlights,
+            &mut adjustments.shadows,
+            &mut adjustments.whites,
+            &mut adjustments.blacks,
+            &mut adjustments.saturation,
+            &mut adjustments.vibrance,
+        ] {
+            *v = clamp(*v, -100.0, 100.0);
+        }
+        adjustments.tone_curve.truncate(MAX_CURVE_POINTS);
+        for (x, y) in &mut adjustments.tone_curve {
+            *x = clamp(*x, 0.0, 1.0);
+            *y = clamp(*y, 0.0, 1.0);
+        }
+    }
+}
+
+/// 色调曲线最多保留的控制点数
+const MAX_CURVE_POINTS: usize = 32;
+
+/// 限制取值范围，NaN 时取范围内最接近 0 的值
+fn clamp(value: f32, min: f32, max: f32) -> f32 {
+    if value.is_nan() {
+        0.0f32.clamp(min, max)
+    } else {
+        value.clamp(min, max)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProcessOptionsBuilder {
+    options: ProcessOptions,
+}
+
+impl ProcessOptionsBuilder {
+    pub fn white_balance(mut self, white_balance: WhiteBalance) -> Self {
+        self.options.white_balance = white_balance;
+        self
+    }
+
+    pub fn exposure(mut self, exposure: Exposure) -> Self {
+        self.options.exposure = exposure;
+        self
+    }
+
+    pub fn metering(mut self, metering: Metering) -> Self {
+        self.options.metering = metering;
+        self
+    }
+
+    pub fn denoise(mut self, denoise: Denoise) -> Self {
+        self.options.denoise = denoise;
+        self
+    }
+
+    pub fn noise_reduction(mut self, noise_reduction: NoiseReduction) -> Self {
+        self.options.noise_reduction = noise_reduction;
+        self
+    }
+
+    pub fn lens_correction(mut self, lens_correction: LensCorrection) -> Self {
+        self.options.lens_correction = lens_correction;
+        self
+    }
+
+    pub fn highlight(mut self, highlight: Highlight) -> Self {
+        self.options.highlight = highlight;
+        self
+    }
+
+    pub fn exp_preserve(mut self, exp_preserve: f32) -> Self {
+        self.options.exp_preserve = exp_preserve;
+        self
+    }
+
+    pub fn half_size(mut self, half_size: bool) -> Self {
+        self.options.half_size = half_size;
+        self
+    }
+
+    pub fn demosaic(mut self, demosaic: Demosaic) -> Self {
+        self.options.demosaic = demosaic;
+        self
+    }
+
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.options.format = format;
+        self
+    }
+
+    /// 根据输出文件的扩展名设置输出格式，无法识别时返回错误
+    pub fn format_from_path(mut self, path: &str) -> Result<Self, RawError> {
+        self.options.format = OutputFormat::from_path(path)?;
+        Ok(self)
+    }
+
+    pub fn bit_depth(mut self, bit_depth: BitDepth) -> Self {
+        self.options.bit_depth = bit_depth;
+        self
+    }
+
+    pub fn color_space(mut self, color_space: ColorSpace) -> Self {
+        self.options.color_space = color_space;
+        self
+    }
+
+    pub fn tone_mapping(mut self, tone_mapping: ToneMapping) -> Self {
+        self.options.tone_mapping = tone_mapping;
+        self
+    }
+
+    pub fn adjustments(mut self, adjustments: Adjustments) -> Self {
+        self.options.adjustments = adjustments;
+        self
+    }
+
+    pub fn sharpening(mut self, sharpening: Sharpening) -> Self {
+        self.options.sharpening = sharpening;
+        self
+    }
+
+    pub fn geometry(mut self, geometry: Geometry) -> Self {
+        self.options.geometry = Some(geometry);
+        self
+    }
+
+    pub fn quality(mut self, quality: u8) -> Self {
+        self.options.quality = quality;
+        self
+    }
+
+    pub fn embed_exif(mut self, embed_exif: bool) -> Self {
+        self.options.embed_exif = embed_exif;
+        self
+    }
+
+    pub fn strip_gps(mut self, strip_gps: bool) -> Self {
+        self.options.strip_gps = strip_gps;
+        self
+    }
+
+    pub fn orientation(mut self, orientation: Orientation) -> Self {
+        self.options.orientation = orientation;
+        self
+    }
+
+    pub fn frame(mut self, font_file: impl Into<String>) -> Self {
+        self.options.frame = Some(FrameOptions {
+            font_file: font_file.into(),
+        });
+        self
+    }
+
+    pub fn lut(mut self, lut: impl Into<String>) -> Self {
+        self.options.lut = Some(lut.into());
+        self
+    }
+
+    pub fn lut_intensity(mut self, lut_intensity: f32) -> Self {
+        self.options.lut_intensity = lut_intensity;
+        self
+    }
+
+    pub fn second_lut(mut self, lut: impl Into<String>) -> Self {
+        self.options.second_lut = Some(lut.into());
+        self
+    }
+
+    pub fn second_lut_intensity(mut self, second_lut_intensity: f32) -> Self {
+        self.options.second_lut_intensity = second_lut_intensity;
+        self
+    }
+
+    pub fn lut_interpolation(mut self, lut_interpolation: LutInterpolation) -> Self {
+        self.options.lut_interpolation = lut_interpolation;
+        self
+    }
+
+    pub fn build(mut self) -> ProcessOptions {
+        self.options.sanitize();
+        self.options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_clamps_deserialized_options() {
+        let mut options: ProcessOptions = serde_json::from_str(
+            r#"{
+                "quality": 0,
+                "exposure": {"manual": 100.0},
+                "lens_correction": {"ca_red": 0.0, "ca_blue": 5.0},
+                "sharpening": {"amount": -1.0, "radius": 1e30},
+                "tone_mapping": {"shift": 0.0}
+            }"#,
+        )
+        .unwrap();
+        options.lut_intensity = f32::NAN;
+        options.adjustments.contrast = f32::INFINITY;
+        options.sanitize();
+        assert_eq!(options.quality, 1);
+        assert_eq!(options.exposure, Exposure::Manual(3.0));
+        assert_eq!(options.lens_correction.ca_red, 0.99);
+        assert_eq!(options.lens_correction.ca_blue, 1.01);
+        assert_eq!(options.sharpening.amount, 0.0);
+        assert_eq!(options.sharpening.radius, 5.0);
+        assert_eq!(options.tone_mapping.shift, 0.1);
+        assert_eq!(options.lut_intensity, 0.0);
+        assert_eq!(options.adjustments.contrast, 100.0);
+    }
+}
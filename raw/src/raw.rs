@@ -1,101 +1,151 @@
-use std::ffi::{c_char, c_int, CString};
-use std::{
-    default,
-    fs::{self, File},
-    io::{BufReader, BufWriter, Write},
-    path::Path,
-};
+use std::ffi::c_char;
+use std::fs;
 
-use image::{DynamicImage, ExtendedColorType, ImageBuffer, ImageEncoder, ImageReader};
+use image::{ImageBuffer, ImageReader, Rgb};
 use img_frame::get_frame;
-use img_parts::{jpeg::Jpeg, Bytes, ImageEXIF};
-use libraw_rs_vendor::{
-    libraw_close, libraw_data_t, libraw_dcraw_clear_mem, libraw_dcraw_make_mem_image, libraw_dcraw_process, libraw_init, libraw_open_file, libraw_processed_image_t, libraw_unpack, LibRaw_errors_LIBRAW_SUCCESS
-};
-use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use chrono::{TimeZone, Local};
 
-use exif::experimental::Writer;
-use exif::{Field, In, Tag, Value};
-
-use jpeg_encoder;
-use webp::Encoder;
 mod lut3d;
 mod img_frame;
+mod adjustments;
+mod auto_exposure;
+mod clipping;
+mod decoder;
+mod encode;
+mod error;
+mod geometry;
+mod icc;
+mod lens_correction;
+mod lut_cache;
+mod lut_loader;
+mod metadata;
+mod noise_reduction;
+mod options;
+mod sharpen;
+mod tone_map;
+mod white_balance;
+pub use crate::clipping::clipping_mask;
+pub use crate::decoder::{ProcessedImage, RawDecoder};
+pub use crate::encode::encode;
+pub use crate::error::{LutError, RawError};
+pub use crate::lut3d::{Lut, LutSample};
+pub use crate::lut_cache::LutCache;
+pub use crate::lut_loader::{LutFormat, LutLoader};
+pub use crate::metadata::{GpsInfo, SourceExif};
+use crate::metadata::orientation_from_flip;
+pub use crate::options::{
+    Adjustments, AspectRatio, BitDepth, ColorSpace, CropRect, Demosaic, Denoise, Exposure,
+    FrameOptions, Geometry, Highlight, LensCorrection, LutInterpolation, Metering, NoiseReduction,
+    Orientation, OutputFormat, ProcessOptions, ProcessOptionsBuilder, Sharpening, ToneMapOperator,
+    ToneMapping, WhiteBalance,
+};
+use crate::adjustments::apply_adjustments;
+use crate::auto_exposure::{auto_exposure_ev, EV_RANGE};
+use crate::geometry::{apply_geometry, Transform};
 use crate::img_frame::gen_frame_img;
-use crate::lut3d::{interp_8_tetrahedral, parse_cube};
+use crate::lens_correction::correct_lens;
+use crate::noise_reduction::reduce_noise;
+use crate::sharpen::sharpen;
+use crate::tone_map::apply_tone_mapping;
+use crate::white_balance::{grey_box, temperature_multipliers};
+
+/// 处理流程中使用的 16 位 RGB 图像，仅在编码时量化
+pub type Rgb16Image = ImageBuffer<Rgb<u16>, Vec<u16>>;
 
 pub struct RawData {
-    data: Vec<u8>,
+    data: Vec<u16>,
     width: i32,
     height: i32,
     colors: i32,
-    iso: f32,
-    aperture: f32,
-    shutter: f32,
-    focal_len: u16,
-    shooting_date: String,
+    exif: Myexif,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Myexif {
     pub iso: f32,
     pub aperture: f32,
     pub shutter: f32,
+    /// 35mm 等效焦距
     pub focal_len: u16,
     pub shooting_date:String,
+    pub make: String,
+    pub model: String,
+    pub lens: String,
+    /// 实际焦距，单位 mm
+    pub focal_length: f32,
+    /// 曝光补偿，单位 EV
+    pub exposure_bias: f32,
+    pub gps: Option<GpsInfo>,
+    /// exif 方向值 1-8
+    pub orientation: u16,
+    /// 按方向旋转后的图像尺寸
+    pub width: u32,
+    pub height: u32,
+    /// 带时区的拍摄时间，RFC 3339 格式
+    pub timestamp: String,
+    /// 原文件中的完整 exif，仅用于写入输出图像
+    #[serde(skip)]
+    pub source: SourceExif,
 }
 
-fn exposure_shift(data: &[u8]) -> f32 {
-    // let start = Instant::now();
-    let mut v = 0.0;
-    let (m, j) = data
-        .par_iter()
-        .filter(|&i| (*i > 20) && (*i < 220))
-        .map(|&i| (i as i32, 1))
-        .reduce(|| (0, 0), |acc, x| [acc.0 + x.0, acc.1 + x.1].into());
-
-    // let duration = start.elapsed();
-
-    // // 输出运行时间
-    // println!("运行时间: {:?}", duration);
-
-    // let start = Instant::now();
-
-    // let mut m = 0;
-    // let mut j = 0;
-    // for i in data.iter(){
-    //     if *i < 220 && *i > 20 {
-    //         m += *i as i32;
-    //         j += 1;
-    //     }
-    // }
-    // let duration = start.elapsed();
-
-    // // 输出运行时间
-    // println!("运行时间: {:?}", duration);
-    // m = m/j;
-
-    let mm = m / j;
-    if mm < 120 && mm > 60 {
-        v = f32::powf(2.0, 140.0 / mm as f32);
-        if 140 < mm {
-            v = -v;
-        }
+impl Myexif {
+    /// 返回去除 GPS 信息后的副本
+    pub fn without_gps(&self) -> Myexif {
+        let mut exif = self.clone();
+        exif.gps = None;
+        exif.source.strip_gps();
+        exif
     }
-    v
 }
 
-fn generate_gamma_lut(lut: &mut [u8], gamma: f32) {
-    println!("{:?}", gamma);
-    for i in 0..=TBLN {
-        lut[i] = ((i as f32 / 255.0).powf(1.0 / gamma) * 255.0).round() as u8;
-    }
+/// 将 libraw 中以 0 结尾的字符数组转换为字符串
+fn c_str(chars: &[c_char]) -> String {
+    let bytes: Vec<u8> = chars.iter().take_while(|&&c| c != 0).map(|&c| c as u8).collect();
+    String::from_utf8_lossy(&bytes).trim().to_string()
 }
 
-const TBLN: usize = 255;
+/// 读取拍摄信息，libraw 未提供的曝光补偿和时区取自原文件 exif
+fn read_exif(decoder: &RawDecoder, source: SourceExif) -> Myexif {
+    let other = decoder.other();
+    let lens = decoder.lens();
+    let sizes = decoder.sizes();
+    // libraw 按本机时区将拍摄时间转换为时间戳，这里先还原出相机记录的本地时间
+    let local = Local
+        .timestamp_opt(other.timestamp as i64, 0)
+        .single()
+        .unwrap_or_default();
+    let datetime = source
+        .offset_time()
+        .and_then(|offset| offset.from_local_datetime(&local.naive_local()).single())
+        .unwrap_or_else(|| local.fixed_offset());
+    let (width, height) = if sizes.flip & 4 != 0 {
+        (sizes.height as u32, sizes.width as u32)
+    } else {
+        (sizes.width as u32, sizes.height as u32)
+    };
+
+    Myexif {
+        iso: other.iso_speed,
+        aperture: other.aperture,
+        shutter: other.shutter,
+        focal_len: lens.FocalLengthIn35mmFormat,
+        shooting_date: local.format("%Y-%m-%d %H:%M:%S").to_string(),
+        make: c_str(&decoder.idata().make),
+        model: c_str(&decoder.idata().model),
+        lens: c_str(&lens.Lens),
+        focal_length: other.focal_len,
+        exposure_bias: source.exposure_bias().unwrap_or_default(),
+        gps: GpsInfo::from_libraw(&other.parsed_gps),
+        orientation: orientation_from_flip(sizes.flip),
+        width,
+        height,
+        timestamp: datetime.to_rfc3339(),
+        source,
+    }
+}
 
 pub fn add_frame(old_path:String,new_path:String,text: String,font_path:String){
     // DynamicImage::o
@@ -105,295 +155,255 @@ pub fn add_frame(old_path:String,new_path:String,text: String,font_path:String){
     println!("{:?}",aaa);
 }
 
-fn generate_linear_lut(lut: &mut [u8], shift: f32, smooth: f32) {
-    let x1: f32;
-    let x2: f32 = TBLN as f32;
-    let y1: f32;
-    let y2: f32;
-
-    let cstops = shift.ln() / 2.0f32.ln();
-    let room = cstops * 2.0;
-    let roomlin = 2.0f32.powf(room);
-    x1 = (x2 + 1.0) / roomlin - 1.0;
-    y1 = x1 * shift;
-    y2 = x2 * (1.0 + (1.0 - smooth) * (shift - 1.0));
-    let sq3x = (x1 * x1 * x2).powf(1.0 / 3.0);
-    let b = (y2 - y1 + shift * (3.0 * x1 - 3.0 * sq3x)) / (x2 + 2.0 * x1 - 3.0 * sq3x);
-    let a = (shift - b) * 3.0 * (x1 * x1).powf(1.0 / 3.0);
-    let cc = y2 - a * x2.powf(1.0 / 3.0) - b * x2;
-
-    for i in 0..=TBLN {
-        let x = i as f32;
-        let y = a * x.powf(1.0 / 3.0) + b * x + cc;
-        if i < x1 as usize {
-            lut[i] = (x * shift) as u8;
-        } else {
-            lut[i] = if y < 0.0 {
-                0
-            } else if y > TBLN as f32 {
-                TBLN as u8
-            } else {
-                y as u8
-            };
-        }
-    }
+/// 是否按 Raw 文件记录的方向旋转像素
+///
+/// 相框需要按显示方向排版，几何变换的参数也相对显示方向，此时始终旋转；
+/// 不嵌入 exif 时无法写入方向标签，也需要旋转
+fn rotate_pixels(options: &ProcessOptions) -> bool {
+    options.orientation == Orientation::Rotate
+        || !options.embed_exif
+        || options.frame.is_some()
+        || options.geometry.is_some()
 }
 
-fn apply_exp_correction(image: &mut Vec<u8>, shift: f32, luttype: &str) {
-    let mut lut = [0u8; TBLN + 1];
-    let smooth = 0.9f32; // 示例值
-    match luttype {
-        "gamma" => {
-            generate_gamma_lut(&mut lut, shift);
-        }
-        "linear" => {
-            generate_linear_lut(&mut lut, shift, smooth);
+fn set_white_balance(decoder: &mut RawDecoder, options: &ProcessOptions, flip: i32) {
+    match options.white_balance {
+        WhiteBalance::Auto => decoder.params().use_auto_wb = 1,
+        WhiteBalance::Camera => decoder.params().use_camera_wb = 1,
+        WhiteBalance::Temperature { kelvin, tint } => {
+            match temperature_multipliers(&decoder.color().cam_xyz, kelvin, tint) {
+                Some(mul) => decoder.params().user_mul = mul,
+                // 没有色彩矩阵的相机无法换算色温
+                None => decoder.params().use_camera_wb = 1,
+            }
         }
-        _default => {
-            generate_linear_lut(&mut lut, shift, smooth);
+        WhiteBalance::Multipliers(mul) => decoder.params().user_mul = mul,
+        WhiteBalance::GreyPoint { x, y } => {
+            // 灰点坐标相对几何变换后的图像，先映射回原图
+            let (x, y) = match &options.geometry {
+                Some(geometry) => {
+                    let sizes = decoder.sizes();
+                    let (width, height) = if flip & 4 != 0 {
+                        (sizes.height, sizes.width)
+                    } else {
+                        (sizes.width, sizes.height)
+                    };
+                    Transform::new(geometry, width as usize, height as usize).source_relative(x, y)
+                }
+                None => (x, y),
+            };
+            decoder.params().greybox = grey_box(decoder.sizes(), flip, x, y);
+            decoder.params().use_auto_wb = 1;
         }
     }
-
-    for channel in image.iter_mut() {
-        *channel = lut[*channel as usize];
-    }
 }
 
-fn read_raw(input: &str, wb: bool, half_size: bool, exp_shift: f32, threshold: i32) -> RawData {
-    let libraw_data: *mut libraw_data_t = unsafe { libraw_init(0) };
-    let rust_var = 0;
-    let input_c_str = CString::new(input).unwrap();
-    let input_c_world: *const c_char = input_c_str.as_ptr() as *const c_char;
-    unsafe {
-        libraw_open_file(libraw_data, input_c_world);
-        libraw_unpack(libraw_data);
-        (*(libraw_data)).params.exp_correc = 1;
-        (*(libraw_data)).params.exp_preser = 0.8;
-        let iso = (*(libraw_data)).other.iso_speed;
-        let aperture = (*(libraw_data)).other.aperture;
-        let shutter = (*(libraw_data)).other.shutter;
-        let timestamp = (*(libraw_data)).other.timestamp;
-        let focal_len = (*(libraw_data)).lens.FocalLengthIn35mmFormat;
-        if wb {
-            (*(libraw_data)).params.use_auto_wb = 1;
-        } else {
-            (*(libraw_data)).params.use_camera_wb = 1;
-        }
-        if threshold > -1 {
-            (*(libraw_data)).params.threshold = threshold as f32;
-        } else {
-            (*(libraw_data)).params.threshold = 256.0 * ((*(libraw_data)).other.iso_speed / 400.0);
-        }
-        if exp_shift >= -2.0 {
-            (*(libraw_data)).params.exp_shift = f32::powf(2.0, exp_shift);
-        } else {
-            (*(libraw_data)).params.half_size = 1;
-            libraw_dcraw_process(libraw_data);
-
-            let ptr: *mut c_int = rust_var as *mut c_int;
-            let img = libraw_dcraw_make_mem_image(libraw_data, ptr);
-            let raw_data = (*img).data.as_ptr();
-            let raw_size = (*img).data_size;
-            let _width = (*img).width as i32;
-            let _height = (*img).height as i32;
-            let _colors = (*img).colors as i32;
-            // 使用指针和大小创建一个 Rust 切片
-            let _rawdata = std::slice::from_raw_parts(raw_data, raw_size as usize);
-            let v = exposure_shift(_rawdata);
-            (*(libraw_data)).params.exp_shift = v;
-            libraw_dcraw_clear_mem(img);
-        }
-        (*(libraw_data)).params.half_size = half_size as i32;
-        libraw_dcraw_process(libraw_data);
+fn read_raw(
+    mut decoder: RawDecoder,
+    options: &ProcessOptions,
+    source: SourceExif,
+) -> Result<RawData, RawError> {
+    decoder.params().output_bps = 16;
+    decoder.params().exp_correc = 1;
+    decoder.params().exp_preser = options.exp_preserve.clamp(0.0, 1.0);
+    decoder.params().highlight = options.highlight.mode();
+    decoder.params().output_color = options.color_space.output_color();
+    let (power, slope) = options.color_space.gamma();
+    decoder.params().gamm[0] = power;
+    decoder.params().gamm[1] = slope;
+    let mut exif = read_exif(&decoder, source);
+    let iso = exif.iso;
+    let flip = if rotate_pixels(options) {
+        decoder.sizes().flip
+    } else {
+        exif.source.set_orientation(exif.orientation);
+        0
+    };
+    decoder.params().user_flip = flip;
+    set_white_balance(&mut decoder, options, flip);
+    decoder.params().threshold = match options.denoise {
+        Denoise::Auto => 256.0 * (iso / 400.0),
+        Denoise::Manual(threshold) => threshold,
+    };
+    let ev = match options.exposure {
+        Exposure::Manual(ev) => ev.clamp(EV_RANGE.0, EV_RANGE.1),
+        Exposure::Auto => metered_ev(&mut decoder, options)?,
+    };
+    decoder.params().exp_shift = f32::powf(2.0, ev);
+    decoder.params().half_size = options.half_size as i32;
+    decoder.params().user_qual = options.demosaic.user_qual();
+    decoder.params().fbdd_noiserd = options.noise_reduction.fbdd as i32;
+    decoder.params().med_passes = options.noise_reduction.median_passes as i32;
+    // 与 dcraw -C 一致，aber 为缩放倍率的倒数
+    decoder.params().aber[0] = 1.0 / options.lens_correction.ca_red as f64;
+    decoder.params().aber[2] = 1.0 / options.lens_correction.ca_blue as f64;
+    let img = decoder.process()?;
+
+    Ok(RawData {
+        data: img.data_u16(),
+        width: img.width() as i32,
+        height: img.height() as i32,
+        colors: img.colors() as i32,
+        exif,
+    })
+}
 
-        let ptr: *mut c_int = rust_var as *mut c_int;
-        let img = libraw_dcraw_make_mem_image(libraw_data, ptr);
-        let raw_data = (*img).data.as_ptr();
-        let raw_size = (*img).data_size;
-        let _width = (*img).width as i32;
-        let _height = (*img).height as i32;
-        let _colors = (*img).colors as i32;
-        let datetime = Local.timestamp_opt(timestamp, 0).unwrap();
+/// 以当前参数处理半尺寸图像并测光，返回曝光补偿
+fn metered_ev(decoder: &mut RawDecoder, options: &ProcessOptions) -> Result<f32, RawError> {
+    decoder.params().exp_shift = 1.0;
+    decoder.params().half_size = 1;
+    let img = decoder.process()?;
+    Ok(auto_exposure_ev(
+        &img.data_u16(),
+        img.width() as usize,
+        img.height() as usize,
+        options.metering,
+        options.color_space,
+    ))
+}
 
-        // 使用指针和大小创建一个 Rust 切片
-        let _rawdata = std::slice::from_raw_parts(raw_data, raw_size as usize);
+/// Raw 数据来源
+#[derive(Clone, Copy)]
+pub enum RawInput<'a> {
+    Path(&'a str),
+    Buffer(&'a [u8]),
+}
 
-        let rawdata = RawData {
-            data: _rawdata.to_vec(),
-            width: _width,
-            height: _height,
-            colors: _colors,
-            iso,
-            aperture,
-            shutter,
-            focal_len,
-            shooting_date:datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
-        };
-        libraw_close(libraw_data);
-        libraw_dcraw_clear_mem(img);
-        rawdata
+impl<'a> From<&'a str> for RawInput<'a> {
+    fn from(path: &'a str) -> Self {
+        RawInput::Path(path)
     }
 }
 
-fn save(
-    output: String,
-    data: Vec<u8>,
-    width: u32,
-    height: u32,
-    quality: i32,
-    _exif: &Myexif,
-    embed_exif:bool,
-    font_file:&str
-) -> Result<String, String> {
-    let (data,width,height) = if font_file != ""{
-        let exif_str = format!("{}mm f/{} 1/{}s ISO{}",_exif.focal_len,_exif.aperture,(1.0/_exif.shutter).round(),_exif.iso);
-        gen_frame_img(data,width,height,&exif_str,false,true,None,font_file)
+impl<'a> From<&'a [u8]> for RawInput<'a> {
+    fn from(buffer: &'a [u8]) -> Self {
+        RawInput::Buffer(buffer)
     }
-    else{
-        (data,width,height)
-    };
-    match output.split('.').last() {
-        Some(suffix) if suffix == "webp" => {
-            let encoder = Encoder::from_rgb(&data, width, height);
-            let webp = encoder.encode(quality as f32);
-            fs::write(output, &*webp).unwrap();
-            Ok("aaa".to_string())
-        }
-        Some(suffix) if suffix == "jpg" => {
-            {
-                if embed_exif{
-                    let mut buf_writer = BufWriter::new(std::io::Cursor::new(Vec::new()));
-                    let mut encoder =
-                        jpeg_encoder::Encoder::new(&mut buf_writer, quality.try_into().unwrap());
-                    encoder.set_progressive(true);
-                    encoder
-                        .encode(
-                            &data,
-                            width.try_into().unwrap(),
-                            height.try_into().unwrap(),
-                            jpeg_encoder::ColorType::Rgb,
-                        )
-                        .unwrap();
-
-                    let image_desc1 = Field {
-                        tag: Tag::ExposureTime,
-                        ifd_num: In::PRIMARY,
-                        value: Value::Float(vec![_exif.shutter]),
-                    };
-
-                    let image_desc2 = Field {
-                        tag: Tag::FNumber,
-                        ifd_num: In::PRIMARY,
-                        value: Value::Float(vec![_exif.aperture]),
-                    };
-
-                    let image_desc3 = Field {
-                        tag: Tag::FocalLength,
-                        ifd_num: In::PRIMARY,
-                        value: Value::Short(vec![_exif.focal_len]),
-                    };
-
-                    let image_desc4 = Field {
-                        tag: Tag::PhotographicSensitivity,
-                        ifd_num: In::PRIMARY,
-                        value: Value::Long(vec![_exif.iso as u32]),
-                    };
-
-                    let mut writer = Writer::new();
-                    let mut buf = std::io::Cursor::new(Vec::new());
-                    writer.push_field(&image_desc1);
-                    writer.push_field(&image_desc2);
-                    writer.push_field(&image_desc3);
-                    writer.push_field(&image_desc4);
-                    writer.write(&mut buf, false).unwrap();
+}
 
-                    let output2 = File::create(&output).unwrap();
+fn open_decoder(input: RawInput) -> Result<RawDecoder, RawError> {
+    match input {
+        RawInput::Path(path) => RawDecoder::open(path),
+        RawInput::Buffer(buffer) => RawDecoder::from_buffer(buffer),
+    }
+}
 
-                    let cursor = buf_writer.into_inner().unwrap();
-                    let buffer = cursor.into_inner();
+/// 计算自动曝光的补偿值，单位 EV，可在界面中显示并作为手动曝光的初始值
+pub fn auto_exposure<'a>(
+    input: impl Into<RawInput<'a>>,
+    options: &ProcessOptions,
+) -> Result<f32, RawError> {
+    let mut decoder = open_decoder(input.into())?;
+    decoder.params().output_bps = 16;
+    decoder.params().output_color = options.color_space.output_color();
+    let (power, slope) = options.color_space.gamma();
+    decoder.params().gamm[0] = power;
+    decoder.params().gamm[1] = slope;
+    let flip = if rotate_pixels(options) { decoder.sizes().flip } else { 0 };
+    decoder.params().user_flip = flip;
+    set_white_balance(&mut decoder, options, flip);
+    metered_ev(&mut decoder, options)
+}
 
-                    let mut jpeg = Jpeg::from_bytes(buffer.into()).unwrap();
-                    jpeg.set_exif(Some(Bytes::copy_from_slice(&buf.into_inner())));
-                    jpeg.encoder().write_to(output2).unwrap();
-                }
-                else{
-                    let mut buf_writer = BufWriter::new(File::create(&output).unwrap());
-                    let mut encoder =
-                        jpeg_encoder::Encoder::new(&mut buf_writer, quality.try_into().unwrap());
-                    encoder.set_progressive(true);
-                    encoder
-                        .encode(
-                            &data,
-                            width.try_into().unwrap(),
-                            height.try_into().unwrap(),
-                            jpeg_encoder::ColorType::Rgb,
-                        )
-                        .unwrap();
-                }
-            }
-            
-            Ok("aaa".to_string())
-        }
-        Some(_) => {
-            let img2: image::DynamicImage = ImageBuffer::from_raw(width, height, data)
-                .map(DynamicImage::ImageRgb8)
-                .expect("转换错误！");
-            img2.save("out.jpg").unwrap();
-            Ok("aaa".to_string())
-        }
-        None => Err("aaa".to_string()),
+/// 计算转换参数中白平衡模式对应的 RGBG 通道倍率，绿色通道为 1
+///
+/// 用于将灰点取样等依赖单张图像的白平衡固定下来，在批量处理时复用
+pub fn white_balance_multipliers<'a>(
+    input: impl Into<RawInput<'a>>,
+    options: &ProcessOptions,
+) -> Result<[f32; 4], RawError> {
+    let mut decoder = open_decoder(input.into())?;
+    let flip = if rotate_pixels(options) { decoder.sizes().flip } else { 0 };
+    set_white_balance(&mut decoder, options, flip);
+    decoder.params().half_size = 1;
+    decoder.process()?;
+    // libraw 处理后 pre_mul 中保存实际使用的倍率
+    let pre_mul = decoder.color().pre_mul;
+    if pre_mul[1] <= 0.0 {
+        return Err(RawError::CorruptData);
+    }
+    let mut mul = pre_mul.map(|v| v / pre_mul[1]);
+    if mul[3] <= 0.0 {
+        mul[3] = 1.0;
     }
+    Ok(mul)
 }
 
-pub fn raw_process(
-    input: String,
-    output: String,
-    lut: String,
-    wb: bool,
-    half_size: bool,
-    exp_shift: f32,
-    threshold: i32,
-    quality: i32,
-    embed_exif:bool,
-    font_file:&str,
-) -> Result<Myexif, String> {
-    if let Ok(_) = fs::metadata(&input) {
-        let rawdata = read_raw(&input, wb, half_size, exp_shift, threshold);
-        let _exif = Myexif {
-            iso: rawdata.iso,
-            aperture: rawdata.aperture,
-            shutter: rawdata.shutter,
-            focal_len: rawdata.focal_len,
-            shooting_date:rawdata.shooting_date,
+/// 解码并处理 Raw 数据，返回处理后的图像和 exif，不写入文件
+pub fn process_to_image<'a>(
+    input: impl Into<RawInput<'a>>,
+    options: &ProcessOptions,
+) -> Result<(Rgb16Image, Myexif), RawError> {
+    let input = input.into();
+    let source = match input {
+        RawInput::Path(path) => SourceExif::read(path)?,
+        RawInput::Buffer(buffer) => SourceExif::parse(buffer),
+    };
+    let decoder = open_decoder(input)?;
+    let rawdata = read_raw(decoder, options, source)?;
+    let _exif = rawdata.exif;
+    let mut data = rawdata.data;
+    let (mut width, mut height) = (rawdata.width as usize, rawdata.height as usize);
+    reduce_noise(&mut data, width, height, &options.noise_reduction);
+    let mut data = correct_lens(
+        data,
+        width,
+        height,
+        &options.lens_correction,
+        &_exif,
+        options.color_space,
+    )?;
+    if let Some(geometry) = &options.geometry {
+        (data, width, height) = apply_geometry(data, width, height, geometry);
+    }
+    apply_tone_mapping(&mut data, &options.tone_mapping, options.color_space);
+    apply_adjustments(&mut data, &options.adjustments);
+    // 两个 lut 依次应用，每个按各自的强度与应用前的图像混合
+    for (lut, intensity) in [
+        (&options.lut, options.lut_intensity),
+        (&options.second_lut, options.second_lut_intensity),
+    ] {
+        let Some(lut) = lut.as_deref().filter(|lut| fs::metadata(lut).is_ok()) else {
+            continue;
         };
-        if let Ok(_) = fs::metadata(&lut) {
-            let lut3d = parse_cube(&lut).unwrap();
-            let img = interp_8_tetrahedral(lut3d, rawdata.data, rawdata.width, rawdata.colors);
-            save(
-                output,
-                img,
-                rawdata.width.try_into().unwrap(),
-                rawdata.height.try_into().unwrap(),
-                quality,
-                &_exif,
-                embed_exif,
-                font_file,
-            );
-            Ok(_exif)
-        } else {
-            save(
-                output,
-                rawdata.data,
-                rawdata.width.try_into().unwrap(),
-                rawdata.height.try_into().unwrap(),
-                quality,
-                &_exif,
-                embed_exif,
-                font_file,
-            );
-            Ok(_exif)
+        if intensity > 0.0 {
+            let lut = LutCache::global().get(lut)?;
+            let intensity = intensity.min(100.0) / 100.0;
+            data = lut.apply(&data, width, rawdata.colors as usize, options.lut_interpolation, intensity);
         }
-    } else {
-        Err("aaa".to_string())
     }
+    sharpen(&mut data, width, height, &options.sharpening);
+    let img = Rgb16Image::from_raw(width as u32, height as u32, data)
+        .ok_or(RawError::CorruptData)?;
+    let img = if let Some(frame) = &options.frame {
+        let exif_str = format!("{}mm f/{} 1/{}s ISO{}",_exif.focal_len,_exif.aperture,(1.0/_exif.shutter).round(),_exif.iso);
+        gen_frame_img(img,&exif_str,false,true,None,&frame.font_file)
+    }
+    else{
+        img
+    };
+    Ok((img, _exif))
+}
+
+/// 转换 Raw 文件并写入 output，输出文件的扩展名与 options.format 不一致时返回错误
+pub fn raw_process(
+    input: &str,
+    output: &str,
+    options: &ProcessOptions,
+) -> Result<Myexif, RawError> {
+    fs::metadata(input)?;
+    options.format.check_path(output)?;
+    let (img, _exif) = process_to_image(input, options)?;
+    let stripped = options.strip_gps.then(|| _exif.without_gps());
+    let exif = options.embed_exif.then(|| stripped.as_ref().unwrap_or(&_exif));
+    let data = encode(
+        &img,
+        options.format,
+        options.quality,
+        options.bit_depth,
+        options.color_space,
+        exif,
+    )?;
+    fs::write(output, data)?;
+    Ok(_exif)
 }
@@ -0,0 +1,132 @@
+use rayon::prelude::*;
+
+use crate::options::Sharpening;
+
+const KR: f32 = 0.2126;
+const KG: f32 = 0.7152;
+const KB: f32 = 0.0722;
+
+/// 对亮度做 USM 锐化和清晰度调整，差值按比例加回 RGB 三个通道，避免产生色边
+pub(crate) fn sharpen(data: &mut [u16], width: usize, height: usize, sharpening: &Sharpening) {
+    if (sharpening.amount <= 0.0 && sharpening.clarity == 0.0) || width == 0 || height == 0 {
+        return;
+    }
+    let luma: Vec<f32> = data
+        .par_chunks_exact(3)
+        .map(|p| (KR * p[0] as f32 + KG * p[1] as f32 + KB * p[2] as f32) / 65535.0)
+        .collect();
+    let mut detail = vec![0.0f32; luma.len()];
+
+    if sharpening.amount > 0.0 {
+        let amount = sharpening.amount / 100.0;
+        let threshold = sharpening.threshold / 255.0;
+        let blurred = gaussian_blur(&luma, width, height, sharpening.radius);
+        detail.par_iter_mut().enumerate().for_each(|(i, d)| {
+            let diff = luma[i] - blurred[i];
+            if diff.abs() > threshold {
+                *d += diff * amount;
+            }
+        });
+    }
+
+    if sharpening.clarity != 0.0 {
+        // 清晰度使用与图像尺寸成比例的大半径，只增强中间调的局部对比度
+        let amount = sharpening.clarity / 100.0 * 0.5;
+        let sigma = width.max(height) as f32 / 200.0;
+        let blurred = box_blur(&luma, width, height, sigma);
+        detail.par_iter_mut().enumerate().for_each(|(i, d)| {
+            let y = luma[i];
+            let midtone = 1.0 - (2.0 * y - 1.0).powi(2);
+            *d += (y - blurred[i]) * amount * midtone;
+        });
+    }
+
+    data.par_chunks_exact_mut(3).enumerate().for_each(|(i, p)| {
+        let d = detail[i] * 65535.0;
+        for v in p.iter_mut() {
+            *v = (*v as f32 + d).round().clamp(0.0, 65535.0) as u16;
+        }
+    });
+}
+
+/// 可分离的高斯模糊，核半径取 3 倍 sigma
+fn gaussian_blur(src: &[f32], width: usize, height: usize, sigma: f32) -> Vec<f32> {
+    let sigma = sigma.max(0.1);
+    let radius = (sigma * 3.0).ceil() as isize;
+    let kernel: Vec<f32> = (-radius..=radius)
+        .map(|x| (-((x * x) as f32) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f32 = kernel.iter().sum();
+    let kernel: Vec<f32> = kernel.iter().map(|k| k / sum).collect();
+    let (w, h) = (width as isize, height as isize);
+
+    let mut tmp = vec![0.0; src.len()];
+    tmp.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+        for (x, out) in row.iter_mut().enumerate() {
+            *out = kernel
+                .iter()
+                .enumerate()
+                .map(|(i, k)| {
+                    let xx = (x as isize + i as isize - radius).clamp(0, w - 1) as usize;
+                    src[y * width + xx] * k
+                })
+                .sum();
+        }
+    });
+    let mut dst = vec![0.0; src.len()];
+    dst.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+        for (x, out) in row.iter_mut().enumerate() {
+            *out = kernel
+                .iter()
+                .enumerate()
+                .map(|(i, k)| {
+                    let yy = (y as isize + i as isize - radius).clamp(0, h - 1) as usize;
+                    tmp[yy * width + x] * k
+                })
+                .sum();
+        }
+    });
+    dst
+}
+
+/// 三次盒式模糊近似大半径高斯模糊，耗时与半径无关
+fn box_blur(src: &[f32], width: usize, height: usize, sigma: f32) -> Vec<f32> {
+    // 三次宽度为 2r+1 的盒式模糊方差为 ((2r+1)^2-1)/4
+    let radius = (((4.0 * sigma * sigma + 1.0).sqrt() - 1.0) / 2.0).round().max(1.0) as usize;
+    let mut data = src.to_vec();
+    for _ in 0..3 {
+        data = box_blur_rows(&data, width, radius);
+        data = transpose(&data, width, height);
+        data = box_blur_rows(&data, height, radius);
+        data = transpose(&data, height, width);
+    }
+    data
+}
+
+fn box_blur_rows(src: &[f32], width: usize, radius: usize) -> Vec<f32> {
+    let mut dst = vec![0.0; src.len()];
+    let norm = 1.0 / (2 * radius + 1) as f32;
+    dst.par_chunks_mut(width)
+        .zip(src.par_chunks(width))
+        .for_each(|(out, row)| {
+            let at = |i: isize| row[i.clamp(0, width as isize - 1) as usize];
+            let r = radius as isize;
+            let mut sum: f32 = (-r..=r).map(at).sum();
+            for (x, o) in out.iter_mut().enumerate() {
+                *o = sum * norm;
+                let x = x as isize;
+                sum += at(x + r + 1) - at(x - r);
+            }
+        });
+    dst
+}
+
+fn transpose(src: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let mut dst = vec![0.0; src.len()];
+    dst.par_chunks_mut(height).enumerate().for_each(|(x, col)| {
+        for (y, v) in col.iter_mut().enumerate() {
+            *v = src[y * width + x];
+        }
+    });
+    dst
+}
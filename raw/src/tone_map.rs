@@ -0,0 +1,122 @@
+use rayon::prelude::*;
+
+use crate::options::{ColorSpace, ToneMapOperator, ToneMapping};
+
+const TBLN: usize = u16::MAX as usize;
+
+/// 去马赛克之后对每个通道应用色调映射
+pub(crate) fn apply_tone_mapping(data: &mut [u16], mapping: &ToneMapping, color_space: ColorSpace) {
+    let lut = match mapping.operator {
+        ToneMapOperator::None => return,
+        ToneMapOperator::Gamma => generate_gamma_lut(mapping.shift),
+        ToneMapOperator::Linear => generate_linear_lut(mapping.shift, mapping.smooth),
+        operator => generate_curve_lut(operator, mapping.shift, color_space),
+    };
+    data.par_iter_mut().for_each(|channel| *channel = lut[*channel as usize]);
+}
+
+fn generate_gamma_lut(gamma: f32) -> Vec<u16> {
+    (0..=TBLN)
+        .into_par_iter()
+        .map(|i| ((i as f32 / TBLN as f32).powf(1.0 / gamma) * TBLN as f32).round() as u16)
+        .collect()
+}
+
+/// 暗部线性提升 shift 倍，高光用立方根曲线压缩，smooth 越大保留的高光越多
+fn generate_linear_lut(shift: f32, smooth: f32) -> Vec<u16> {
+    let x2: f32 = TBLN as f32;
+    if shift <= 1.0 {
+        // 不提升曝光时没有需要压缩的高光
+        return (0..=TBLN).map(|i| (i as f32 * shift).round() as u16).collect();
+    }
+
+    let cstops = shift.ln() / 2.0f32.ln();
+    let room = cstops * 2.0;
+    let roomlin = 2.0f32.powf(room);
+    let x1 = (x2 + 1.0) / roomlin - 1.0;
+    let y1 = x1 * shift;
+    let y2 = x2 * (1.0 + (1.0 - smooth) * (shift - 1.0));
+    let sq3x = (x1 * x1 * x2).powf(1.0 / 3.0);
+    let b = (y2 - y1 + shift * (3.0 * x1 - 3.0 * sq3x)) / (x2 + 2.0 * x1 - 3.0 * sq3x);
+    let a = (shift - b) * 3.0 * (x1 * x1).powf(1.0 / 3.0);
+    let cc = y2 - a * x2.powf(1.0 / 3.0) - b * x2;
+
+    (0..=TBLN)
+        .into_par_iter()
+        .map(|i| {
+            let x = i as f32;
+            let y = if x < x1 { x * shift } else { a * x.powf(1.0 / 3.0) + b * x + cc };
+            y.clamp(0.0, x2) as u16
+        })
+        .collect()
+}
+
+/// 在线性光下应用的曲线，输入先乘以 shift，并归一化使原白点映射为白色
+fn generate_curve_lut(operator: ToneMapOperator, shift: f32, color_space: ColorSpace) -> Vec<u16> {
+    let curve = |x: f32| match operator {
+        ToneMapOperator::Filmic => hable(x),
+        ToneMapOperator::Aces => aces(x),
+        _ => reinhard(x, shift),
+    };
+    let white = curve(shift);
+    (0..=TBLN)
+        .into_par_iter()
+        .map(|i| {
+            let x = decode(i as f32 / TBLN as f32, color_space);
+            let y = curve(x * shift) / white;
+            (encode(y.clamp(0.0, 1.0), color_space) * TBLN as f32).round() as u16
+        })
+        .collect()
+}
+
+/// Uncharted 2 的 filmic 曲线
+fn hable(x: f32) -> f32 {
+    const A: f32 = 0.15;
+    const B: f32 = 0.50;
+    const C: f32 = 0.10;
+    const D: f32 = 0.20;
+    const E: f32 = 0.02;
+    const F: f32 = 0.30;
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+/// Narkowicz 对 ACES RRT+ODT 的拟合
+fn aces(x: f32) -> f32 {
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+/// 扩展 Reinhard，white 映射为 1
+fn reinhard(x: f32, white: f32) -> f32 {
+    x * (1.0 + x / (white * white)) / (1.0 + x)
+}
+
+/// 输出色彩空间的传递函数，与 libraw 的 gamm 参数一致
+pub(crate) fn decode(v: f32, color_space: ColorSpace) -> f32 {
+    match color_space {
+        ColorSpace::Srgb | ColorSpace::DisplayP3 => {
+            if v <= 0.04045 {
+                v / 12.92
+            } else {
+                ((v + 0.055) / 1.055).powf(2.4)
+            }
+        }
+        ColorSpace::AdobeRgb => v.powf(563.0 / 256.0),
+        ColorSpace::ProPhoto => v.powf(1.8),
+        ColorSpace::Rec2020Linear => v,
+    }
+}
+
+pub(crate) fn encode(v: f32, color_space: ColorSpace) -> f32 {
+    match color_space {
+        ColorSpace::Srgb | ColorSpace::DisplayP3 => {
+            if v <= 0.0031308 {
+                v * 12.92
+            } else {
+                1.055 * v.powf(1.0 / 2.4) - 0.055
+            }
+        }
+        ColorSpace::AdobeRgb => v.powf(256.0 / 563.0),
+        ColorSpace::ProPhoto => v.powf(1.0 / 1.8),
+        ColorSpace::Rec2020Linear => v,
+    }
+}
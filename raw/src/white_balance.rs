@@ -0,0 +1,63 @@
+use libraw_rs_vendor::libraw_image_sizes_t;
+
+/// 色温的有效范围，超出范围时 Kim 等人的普朗克轨迹近似不再准确
+pub(crate) const KELVIN_RANGE: (f32, f32) = (1667.0, 25000.0);
+
+/// 普朗克轨迹上指定色温的 CIE xy 坐标
+fn planckian_xy(kelvin: f32) -> (f64, f64) {
+    let t = kelvin.clamp(KELVIN_RANGE.0, KELVIN_RANGE.1) as f64;
+    let (t2, t3) = (t * t, t * t * t);
+    let x = if t <= 4000.0 {
+        -0.2661239e9 / t3 - 0.2343589e6 / t2 + 0.8776956e3 / t + 0.179910
+    } else {
+        -3.0258469e9 / t3 + 2.1070379e6 / t2 + 0.2226347e3 / t + 0.240390
+    };
+    let (x2, x3) = (x * x, x * x * x);
+    let y = if t <= 2222.0 {
+        -1.1063814 * x3 - 1.34811020 * x2 + 2.18555832 * x - 0.20219683
+    } else if t <= 4000.0 {
+        -0.9549476 * x3 - 1.37418593 * x2 + 2.09137015 * x - 0.16748867
+    } else {
+        3.0817580 * x3 - 5.87338670 * x2 + 3.75112997 * x - 0.37001483
+    };
+    (x, y)
+}
+
+/// 根据色温和色调计算 RGBG 通道倍率，相机没有色彩矩阵时返回空
+///
+/// 色调为正时偏品红，每 100 使绿色通道倍率减半
+pub(crate) fn temperature_multipliers(cam_xyz: &[[f32; 3]; 4], kelvin: f32, tint: f32) -> Option<[f32; 4]> {
+    let (x, y) = planckian_xy(kelvin);
+    let xyz = [x / y, 1.0, (1.0 - x - y) / y];
+    // 白点在相机色彩空间中的响应
+    let cam = cam_xyz.map(|row| (0..3).map(|i| row[i] as f64 * xyz[i]).sum::<f64>());
+    if cam[..3].iter().any(|&v| v <= 0.0) {
+        return None;
+    }
+    let mut mul = cam.map(|v| if v > 0.0 { (cam[1] / v) as f32 } else { 0.0 });
+    let green = f32::powf(2.0, -tint / 100.0);
+    mul[1] *= green;
+    mul[3] = if mul[3] > 0.0 { mul[3] * green } else { mul[1] };
+    Some(mul)
+}
+
+/// 将显示图像上的相对坐标转换为传感器坐标中的取样区域 [left, top, width, height]
+///
+/// flip 为输出时实际应用的方向，取样区域边长为短边的 2%
+pub(crate) fn grey_box(sizes: &libraw_image_sizes_t, flip: i32, x: f32, y: f32) -> [u32; 4] {
+    let (mut x, mut y) = (x.clamp(0.0, 1.0), y.clamp(0.0, 1.0));
+    if flip & 4 != 0 {
+        std::mem::swap(&mut x, &mut y);
+    }
+    if flip & 2 != 0 {
+        y = 1.0 - y;
+    }
+    if flip & 1 != 0 {
+        x = 1.0 - x;
+    }
+    let (width, height) = (sizes.width as f32, sizes.height as f32);
+    let size = (width.min(height) * 0.02).max(8.0).min(width.min(height));
+    let left = (x * width - size / 2.0).clamp(0.0, width - size);
+    let top = (y * height - size / 2.0).clamp(0.0, height - size);
+    [left as u32, top as u32, size as u32, size as u32]
+}
@@ -40,6 +40,7 @@ pub fn get_db_pool(database:&str) -> Pool {
                 half_size bool NOT NULL,
                 quality BIGINT NOT NULL,
                 lut_id BIGINT,
+                options TEXT,
                 UNIQUE(email)
             );
 
@@ -84,6 +85,7 @@ pub fn get_db_pool(database:&str) -> Pool {
                 file_size BIGINT NOT NULL,
                 mime_type TEXT NOT NULL,
                 exif TEXT,
+                geometry TEXT,
                 FOREIGN KEY (user_id) REFERENCES users(id),
                 FOREIGN KEY (path_id) REFERENCES path(id),
                 UNIQUE(path_id,file_name)
@@ -119,6 +121,12 @@ pub fn get_db_pool(database:&str) -> Pool {
                 storages AS storage_cached ON paths_cached.storage_id = storage_cached.id;
         "#);
     }
+    else{
+        // 旧版本数据库补充新增的列，列已存在时忽略错误
+        let conn = db.get().unwrap();
+        let _ = conn.execute("ALTER TABLE users ADD COLUMN options TEXT", []);
+        let _ = conn.execute("ALTER TABLE images ADD COLUMN geometry TEXT", []);
+    }
     db
     
 }
@@ -152,6 +160,17 @@ pub fn create_tantivy_index(index_path:&str) -> tantivy::Result<Index> {
     schema_builder.add_f64_field("iso", INDEXED |STORED| FAST);
     schema_builder.add_f64_field("aperture", INDEXED | STORED| FAST);
     schema_builder.add_f64_field("shutter", INDEXED | STORED | FAST);
+    schema_builder.add_text_field("make", TEXT | STORED);
+    schema_builder.add_text_field("model", TEXT | STORED);
+    schema_builder.add_text_field("lens", TEXT | STORED);
+    schema_builder.add_f64_field("focal_length", INDEXED | STORED | FAST);
+    schema_builder.add_f64_field("exposure_bias", INDEXED | STORED | FAST);
+    schema_builder.add_f64_field("latitude", INDEXED | STORED | FAST);
+    schema_builder.add_f64_field("longitude", INDEXED | STORED | FAST);
+    schema_builder.add_f64_field("altitude", INDEXED | STORED | FAST);
+    schema_builder.add_i64_field("orientation", INDEXED | STORED | FAST);
+    schema_builder.add_i64_field("width", INDEXED | STORED | FAST);
+    schema_builder.add_i64_field("height", INDEXED | STORED | FAST);
 
 
     let opts = DateOptions::from(INDEXED)
@@ -166,10 +185,20 @@ pub fn create_tantivy_index(index_path:&str) -> tantivy::Result<Index> {
 
     let index_path = Path::new(index_path);
 
-    let index = if index_path.exists() {
-        Index::open_in_dir(index_path)?
+    // 字段有变化时重建索引，启动时会从数据库重新同步
+    let existing = if index_path.exists() {
+        Index::open_in_dir(index_path).ok().filter(|index| index.schema() == schema)
+    } else {
+        None
+    };
+
+    let index = if let Some(index) = existing {
+        index
     } else {
-        std::fs::create_dir_all(index_path);
+        if index_path.exists() {
+            std::fs::remove_dir_all(index_path)?;
+        }
+        std::fs::create_dir_all(index_path)?;
         let index = Index::create_in_dir(index_path, schema.clone())?;
         index
         .tokenizers()
@@ -186,6 +215,17 @@ pub fn create_tantivy_index(index_path:&str) -> tantivy::Result<Index> {
 }
 
 
+/// 拍摄时间的时间戳，旧数据没有带时区的时间，按本地时间解析
+///
+/// 夏令时结束时重复的本地时间取较早的一个，夏令时开始时不存在的时间和无法解析的时间返回空
+fn shooting_timestamp(exif:&Myexif,shooting_time:&str) -> Option<i64>{
+    if let Ok(date_time) = chrono::DateTime::parse_from_rfc3339(&exif.timestamp) {
+        return Some(date_time.timestamp());
+    }
+    let naive_date = chrono::NaiveDateTime::parse_from_str(shooting_time, "%Y-%m-%d %H:%M:%S").ok()?;
+    Local.from_local_datetime(&naive_date).earliest().map(|date_time| date_time.timestamp())
+}
+
 pub fn sync_sqlite_to_tantivy(pool: &Pool, index: &Index) {
     let conn = pool.get().unwrap();
     let mut stmt = conn.prepare("select * from images_view;").unwrap();
@@ -206,6 +246,17 @@ pub fn sync_sqlite_to_tantivy(pool: &Pool, index: &Index) {
     let original_url = schema.get_field("original_url").unwrap();
     let file_name = schema.get_field("file_name").unwrap();
     let shooting_date = schema.get_field("shooting_date").unwrap();
+    let make = schema.get_field("make").unwrap();
+    let model = schema.get_field("model").unwrap();
+    let lens = schema.get_field("lens").unwrap();
+    let focal_length = schema.get_field("focal_length").unwrap();
+    let exposure_bias = schema.get_field("exposure_bias").unwrap();
+    let latitude = schema.get_field("latitude").unwrap();
+    let longitude = schema.get_field("longitude").unwrap();
+    let altitude = schema.get_field("altitude").unwrap();
+    let orientation = schema.get_field("orientation").unwrap();
+    let width = schema.get_field("width").unwrap();
+    let height = schema.get_field("height").unwrap();
 
 
     let mut index_writer = index.writer(50_000_000).unwrap();
@@ -238,11 +289,25 @@ pub fn sync_sqlite_to_tantivy(pool: &Pool, index: &Index) {
             _doc.add_f64(shutter,_exif.shutter.into());
             _doc.add_i64(focal_len,_exif.focal_len.into());
             _doc.add_f64(iso,_exif.iso.into());
-           
-            let naive_date = chrono::NaiveDateTime::parse_from_str(&image.shooting_time, "%Y-%m-%d %H:%M:%S").unwrap();
-            let date_time = Local.from_local_datetime(&naive_date).unwrap();
-    
-            let dd = DateTime::from_timestamp_secs(date_time.timestamp());
+            _doc.add_text(make,_exif.make.clone());
+            _doc.add_text(model,_exif.model.clone());
+            _doc.add_text(lens,_exif.lens.clone());
+            _doc.add_f64(focal_length,_exif.focal_length.into());
+            _doc.add_f64(exposure_bias,_exif.exposure_bias.into());
+            if let Some(gps) = _exif.gps {
+                _doc.add_f64(latitude,gps.latitude);
+                _doc.add_f64(longitude,gps.longitude);
+                _doc.add_f64(altitude,gps.altitude.into());
+            }
+            _doc.add_i64(orientation,_exif.orientation.into());
+            _doc.add_i64(width,_exif.width.into());
+            _doc.add_i64(height,_exif.height.into());
+
+            let Some(timestamp) = shooting_timestamp(&_exif, &image.shooting_time) else {
+                log::warn!("无法解析拍摄时间，跳过索引 {}: {}", image.id, image.shooting_time);
+                continue;
+            };
+            let dd = DateTime::from_timestamp_secs(timestamp);
             _doc.add_date(shooting_date,dd);
     
             index_writer.add_document(_doc);
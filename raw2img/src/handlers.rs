@@ -13,7 +13,7 @@ use jwt_simple::{
     claims::{Claims, NoCustomClaims},
     prelude::{Duration, HS256Key, MACLike},
 };
-use raw::{raw_process,add_frame};
+use raw::{add_frame, Geometry, LutCache, LutFormat, LutLoader, ProcessOptions};
 use rusqlite::named_params;
 use serde::{Deserialize, Serialize};
 use tantivy::Index;
@@ -27,7 +27,7 @@ use std::{
 
 use crate::{
     db::{create_tantivy_index, get_db_pool, sync_sqlite_to_tantivy, Pool},
-    proces::{self, raw2img, scan_files},
+    proces::{self, raw2img, scan_files, LensDb},
     schemas::{
         root::{create_schema, Context, Schema},
         storage,
@@ -63,10 +63,14 @@ struct UploadForm {
 pub struct Parameters {
     pub id: i32,
     pub filename: String,
-    pub lut: String,
-    pub wb: bool,
-    pub exp_shift: f64,
-    pub threshold: i32,
+    /// 未经过 builder 限制取值范围，由 proces 中的 preview_options 调用 sanitize
+    #[serde(flatten)]
+    pub options: ProcessOptions,
+}
+
+/// 当前登录用户的编号，会话已由 authentication 中间件验证
+fn session_user_id(session: &Session) -> Option<i32> {
+    session.get::<String>("userid").ok().flatten()?.parse().ok()
 }
 
 /// GraphQL endpoint
@@ -180,12 +184,13 @@ async fn auth(session: Session, pool: web::Data<Pool>, form: web::Form<FormData2
 }
 
 #[route("/scan", method = "POST")]
-async fn scans(pool: web::Data<Pool>,index: web::Data<Index>, user_id: web::Json<i32>) -> HttpResponse {
+async fn scans(pool: web::Data<Pool>,index: web::Data<Index>,lens_db: web::Data<LensDb>, user_id: web::Json<i32>) -> HttpResponse {
     let _pool = Arc::new(Mutex::new(pool.get_ref().to_owned()));
     let _index = index.get_ref().to_owned();
+    let lens_db = lens_db.get_ref().clone();
     let _handle = thread::spawn(move || {
         let _pool1 = _pool.clone();
-        scan_files(*user_id, _pool1);
+        scan_files(*user_id, &lens_db, _pool1);
         let _pool2 = _pool.lock().unwrap();
         sync_sqlite_to_tantivy(&_pool2, &_index);
     });
@@ -251,14 +256,103 @@ async fn get_image(
 async fn raw2jpg(
     session: Session,
     pool: web::Data<Pool>,
+    lens_db: web::Data<LensDb>,
+    parames: web::Json<Parameters>,
+) -> HttpResponse {
+    let Some(user_id) = session_user_id(&session) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match proces::raw2(parames, user_id, lens_db.get_ref(), pool.get_ref().to_owned()) {
+        Some(res) => HttpResponse::Ok().json(res),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[route("/preview", method = "POST")]
+async fn preview(
+    session: Session,
+    pool: web::Data<Pool>,
+    lens_db: web::Data<LensDb>,
+    parames: web::Json<Parameters>,
+) -> HttpResponse {
+    let Some(user_id) = session_user_id(&session) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match proces::preview(parames, user_id, lens_db.get_ref(), pool.get_ref().to_owned()) {
+        Some((data, content_type)) => HttpResponse::Ok().content_type(content_type).body(data),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[route("/clipping", method = "POST")]
+async fn clipping(
+    session: Session,
+    pool: web::Data<Pool>,
+    lens_db: web::Data<LensDb>,
     parames: web::Json<Parameters>,
 ) -> HttpResponse {
-    match proces::raw2(parames, pool.get_ref().to_owned()) {
+    let Some(user_id) = session_user_id(&session) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match proces::clipping(parames, user_id, lens_db.get_ref(), pool.get_ref().to_owned()) {
         Some(res) => HttpResponse::Ok().json(res),
         None => HttpResponse::NotFound().finish(),
     }
 }
 
+#[route("/wb", method = "POST")]
+async fn white_balance(
+    session: Session,
+    pool: web::Data<Pool>,
+    lens_db: web::Data<LensDb>,
+    parames: web::Json<Parameters>,
+) -> HttpResponse {
+    let Some(user_id) = session_user_id(&session) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match proces::white_balance(parames, user_id, lens_db.get_ref(), pool.get_ref().to_owned()) {
+        Some(mul) => HttpResponse::Ok().json(mul),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[route("/exposure", method = "POST")]
+async fn exposure(
+    session: Session,
+    pool: web::Data<Pool>,
+    lens_db: web::Data<LensDb>,
+    parames: web::Json<Parameters>,
+) -> HttpResponse {
+    let Some(user_id) = session_user_id(&session) else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    match proces::exposure(parames, user_id, lens_db.get_ref(), pool.get_ref().to_owned()) {
+        Some(ev) => HttpResponse::Ok().json(ev),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[route("/geometry/{id}", method = "GET")]
+async fn get_geometry(pool: web::Data<Pool>, id: web::Path<i32>) -> HttpResponse {
+    HttpResponse::Ok().json(proces::image_geometry(id.into_inner(), pool.get_ref()))
+}
+
+/// 保存图像的旋转、拉直和裁切参数，参数为空时清除
+#[route("/geometry", method = "POST")]
+async fn save_geometry(
+    pool: web::Data<Pool>,
+    parames: web::Json<(i32, Option<Geometry>)>,
+) -> HttpResponse {
+    let (id, geometry) = parames.into_inner();
+    match proces::save_geometry(id, geometry.as_ref(), pool.get_ref()) {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(e) => {
+            log::error!("保存几何变换参数失败 {}: {}", id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
 #[route("/save", method = "POST")]
 async fn savejpg(
     session: Session,
@@ -317,21 +411,41 @@ async fn update_lut(
     MultipartForm(form): MultipartForm<UploadForm>,
 ) -> Result<impl Responder, Error> {
     let db_conn = pool.get_ref().to_owned();
+    let user_id = session_user_id(&session).ok_or_else(|| actix_web::error::ErrorUnauthorized("no auth"))?;
+    // 上传到当前用户的 lut 存储，转换时只能使用自己存储中的 lut
     if let Ok((luts_path, storage_id)) = db_conn.get().unwrap().query_row(
-        "select storage_path,id from storages where storage_usage = 'luts';",
-        [],
+        "select storage_path,id from storages where storage_usage = 'luts' and user_id = :user_id;",
+        named_params! {":user_id": &user_id},
         |row| Ok((row.get(0).unwrap(), row.get(1).unwrap())),
     ) {
         let luts_path: String = luts_path;
         let storage_id: i32 = storage_id;
 
+        // 写入存储之前先解析全部文件，任何一个无效都不保存
+        for f in &form.files {
+            let file_name = f.file_name.as_deref().unwrap_or_default();
+            let format = Path::new(file_name)
+                .extension()
+                .and_then(|ext| LutFormat::from_extension(&ext.to_string_lossy()))
+                .ok_or_else(|| {
+                    actix_web::error::ErrorBadRequest(format!("{}: 不支持的 LUT 格式", file_name))
+                })?;
+            let data = fs::read(f.file.path())?;
+            if let Err(e) = LutLoader::parse(&data, format) {
+                log::warn!("拒绝无效的 LUT {}: {}", file_name, e);
+                return Err(actix_web::error::ErrorBadRequest(format!("{}: {}", file_name, e)));
+            }
+        }
+
+        // 同名 lut 重新上传时覆盖文件，保留原有记录
         for f in form.files {
             match db_conn.get().unwrap().execute(
-                "INSERT INTO luts (storage_id, lut_name, comment) VALUES (?1, ?2, ?2)",
+                "INSERT OR IGNORE INTO luts (storage_id, lut_name, comment) VALUES (?1, ?2, ?2)",
                 (&storage_id, &f.file_name.as_ref().unwrap()),
             ) {
-                Ok(_) => {
-                    let path = format!("{}/{}", luts_path, f.file_name.as_ref().unwrap());
+                Ok(inserted) => {
+                    let lut_name = f.file_name.clone().unwrap();
+                    let path = format!("{}/{}", luts_path, lut_name);
                     log::info!("上传存储路径：{}",path);
                     // f.file.persist(path).unwrap();
                     let o_path = f.file.path().to_string_lossy().to_string();
@@ -346,6 +460,14 @@ async fn update_lut(
                             
                         }
                     }
+                    LutCache::global().invalidate(&path);
+                    if inserted == 0 {
+                        // 重新上传时清除使用此 lut 的用户的缓存图像，下次批量转换时重新生成
+                        let _ = db_conn.get().unwrap().execute(
+                            "UPDATE images SET cache_id = NULL WHERE user_id IN (SELECT users.id FROM users, luts WHERE luts.storage_id = :storage_id AND luts.lut_name = :lut_name AND (users.lut_id = luts.id OR CASE WHEN json_valid(users.options) THEN json_extract(users.options, '$.second_lut_id') END = luts.id));",
+                            named_params! {":storage_id": &storage_id, ":lut_name": &lut_name},
+                        );
+                    }
                 }
                 Err(_e) => {
                     log::error!("{:?}", _e);
@@ -396,6 +518,12 @@ pub fn register(config: &mut web::ServiceConfig) {
                 .service(scans)
                 .service(get_image)
                 .service(raw2jpg)
+                .service(preview)
+                .service(white_balance)
+                .service(exposure)
+                .service(get_geometry)
+                .service(save_geometry)
+                .service(clipping)
                 .service(savejpg)
                 .service(update_lut),
         )
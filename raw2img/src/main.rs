@@ -8,7 +8,12 @@ use actix_web_static_files::ResourceFiles;
 
 use clap::{Args, Command,Subcommand, Parser};
 use lazy_static::lazy_static;
-use raw::raw_process;
+use raw::{
+    raw_process, Adjustments, AspectRatio, BitDepth, ColorSpace, CropRect, Demosaic, Denoise,
+    Exposure, Geometry, Highlight, LensCorrection, LutInterpolation, Metering, NoiseReduction,
+    Orientation, OutputFormat, ProcessOptions, RawError, Sharpening, ToneMapOperator, ToneMapping,
+    WhiteBalance,
+};
 
 
 mod db;
@@ -16,7 +21,7 @@ mod handlers;
 mod schemas;
 mod proces;
 
-use self::{db::{create_tantivy_index,sync_sqlite_to_tantivy,get_db_pool}, handlers::register};
+use self::{db::{create_tantivy_index,sync_sqlite_to_tantivy,get_db_pool}, handlers::register, proces::LensDb};
 
 include!(concat!(env!("OUT_DIR"), "/generated.rs"));
 include!(concat!(env!("OUT_DIR"), "/git_info.rs"));
@@ -61,6 +66,10 @@ struct ServerArgs {
     /// 边框内容字体，提供下载转换后添加边框中的字体
     #[arg(short, long ,default_value = "")]
     font_file: String,
+
+    /// lensfun 格式的 XML 数据库文件或目录，用于镜头配置文件校正
+    #[arg(long, default_value = "lensfun")]
+    lens_db: String,
 }
 
 #[derive(Args)]
@@ -74,36 +83,216 @@ struct ConvertArgs {
     output: String,
 
     /// 使用 lut 文件滤镜
-    #[arg(short, long, default_value = "")]
-    lut: String,
+    #[arg(short, long)]
+    lut: Option<String>,
+
+    /// lut 强度（%），值范围 0 到 100
+    #[arg(long, default_value_t = 100.0)]
+    lut_intensity: f32,
+
+    /// 在第一个 lut 之后叠加的 lut 文件
+    #[arg(long)]
+    second_lut: Option<String>,
+
+    /// 第二个 lut 的强度（%），值范围 0 到 100
+    #[arg(long, default_value_t = 100.0)]
+    second_lut_intensity: f32,
+
+    /// lut 插值方式（nearest、trilinear、tetrahedral）
+    #[arg(long, default_value = "tetrahedral")]
+    lut_interpolation: String,
 
     /// 使用自动白平衡或相机白平衡
     #[arg(short, long)]
     auto_wb: bool,
 
+    /// 白平衡色温（K），指定时忽略自动白平衡
+    #[arg(long)]
+    temperature: Option<f32>,
+
+    /// 白平衡色调，值范围 -150 到 150，正值偏品红，需要同时指定色温
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    tint: f32,
+
+    /// 白平衡 RGBG 通道倍率，以逗号分隔，如 2.1,1,1.5,1。指定时忽略色温和自动白平衡
+    #[arg(long, value_delimiter = ',', num_args = 4)]
+    wb_mul: Option<Vec<f32>>,
+
     /// 输出尺寸减半
     #[arg(short, long,default_value_t = false)]
     half_size: bool,
 
-    /// 曝光偏移，值范围为 0.25-8，从降低两档到提升三档。当该值指定时，自动曝光偏移将不起作用
-    #[arg(short, long,default_value_t = -3.0)]
-    exp_shift: f32,
+    /// 曝光补偿（EV），值范围为 -2 到 3，从降低两档到提升三档。当该值指定时，自动曝光偏移将不起作用
+    #[arg(short, long, allow_hyphen_values = true)]
+    exp_shift: Option<f32>,
+
+    /// 自动曝光测光模式（average、center），指定曝光补偿时不起作用
+    #[arg(long, default_value = "center")]
+    metering: String,
+
+    /// 高光恢复模式（clip、unclip、blend、rebuild3 到 rebuild9）
+    #[arg(long, default_value = "clip")]
+    highlight: String,
+
+    /// 提升曝光时保护高光的程度，值范围 0-1
+    #[arg(long, default_value_t = 0.8)]
+    exp_preserve: f32,
+
+    /// 去马赛克算法（linear、vng、ppg、ahd、dcb、dht、aahd），半尺寸输出时不起作用
+    #[arg(long, default_value = "ahd")]
+    demosaic: String,
+
+    /// 色调映射算子（none、gamma、linear、filmic、aces、reinhard）
+    #[arg(long, default_value = "none")]
+    tone_map: String,
+
+    /// 色调映射的曝光倍率，值范围 0.1-8，gamma 算子中为 gamma 值
+    #[arg(long, default_value_t = 1.0)]
+    tone_shift: f32,
+
+    /// linear 色调映射保留高光的程度，值范围 0-1
+    #[arg(long, default_value_t = 0.9)]
+    tone_smooth: f32,
+
+    /// 对比度，值范围 -100 到 100
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    contrast: f32,
+
+    /// 高光，值范围 -100 到 100
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    highlights: f32,
+
+    /// 阴影，值范围 -100 到 100
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    shadows: f32,
+
+    /// 白色色阶，值范围 -100 到 100
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    whites: f32,
+
+    /// 黑色色阶，值范围 -100 到 100
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    blacks: f32,
+
+    /// 饱和度，值范围 -100 到 100
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    saturation: f32,
+
+    /// 自然饱和度，值范围 -100 到 100
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    vibrance: f32,
+
+    /// 色调曲线控制点，格式为 输入:输出，以逗号分隔，范围 0-1，如 0:0,0.25:0.2,0.75:0.8,1:1
+    #[arg(long)]
+    tone_curve: Option<String>,
+
+    /// USM 锐化强度（%），值范围 0-500，在缩放之后应用
+    #[arg(long, default_value_t = 0.0)]
+    sharpen: f32,
+
+    /// USM 锐化半径（像素），值范围 0.3-5
+    #[arg(long, default_value_t = 1.0)]
+    sharpen_radius: f32,
+
+    /// USM 锐化阈值（8 位色阶），值范围 0-255
+    #[arg(long, default_value_t = 0.0)]
+    sharpen_threshold: f32,
 
-    /// 输出质量，值范围 0-100
-    #[arg(short, long,default_value_t = 90)]
-    quality: i32,
+    /// 清晰度，值范围 -100 到 100
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    clarity: f32,
+
+    /// 输出质量，值范围 1-100
+    #[arg(short, long,default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: u8,
 
     /// 降噪参数。当指定该值时，自动降噪将不起作用
-    #[arg(short, long,default_value_t = -1)]
-    noise: i32,
+    #[arg(short, long)]
+    noise: Option<f32>,
+
+    /// 亮度降噪强度，值范围 0-100
+    #[arg(long, default_value_t = 0.0)]
+    nr_luma: f32,
+
+    /// 色度降噪强度，值范围 0-100
+    #[arg(long, default_value_t = 0.0)]
+    nr_chroma: f32,
+
+    /// libraw FBDD 降噪，0 关闭，1 仅亮度，2 亮度和色度
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=2))]
+    fbdd: u8,
+
+    /// 去马赛克后 3x3 中值滤波的次数，值范围 0-10
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=10))]
+    median_passes: u8,
+
+    /// 根据镜头型号从 lensfun 数据库查找配置文件，校正暗角、畸变和色差
+    #[arg(long)]
+    lens_profile: bool,
+
+    /// lensfun 格式的 XML 数据库文件或目录
+    #[arg(long, default_value = "lensfun")]
+    lens_db: String,
+
+    /// 手动暗角补偿，值范围 -100 到 100，正值提亮边角
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    vignetting: f32,
+
+    /// 手动畸变校正，值范围 -100 到 100，正值校正桶形畸变
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    distortion: f32,
+
+    /// 色差校正的红、蓝通道缩放倍率，以逗号分隔，值范围 0.99-1.01，如 1.0005,0.9995
+    #[arg(long, value_delimiter = ',', num_args = 2)]
+    ca: Option<Vec<f32>>,
+
+    /// 顺时针旋转角度（0、90、180、270）
+    #[arg(long, default_value_t = 0)]
+    rotate: u16,
+
+    /// 拉直角度，值范围 -45 到 45，正值顺时针
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    straighten: f32,
+
+    /// 拉直后不自动裁掉边角的空白区域
+    #[arg(long)]
+    no_auto_crop: bool,
+
+    /// 裁切区域，格式为 x,y,宽,高，值为相对旋转和拉直后图像的比例，如 0.1,0.1,0.8,0.8
+    #[arg(long)]
+    crop: Option<String>,
+
+    /// 裁切比例（free、original、1:1、3:2、4:3、5:4、16:9）
+    #[arg(long, default_value = "free")]
+    aspect: String,
 
     /// 是否嵌入exif
     #[arg(short, long ,default_value_t = true)]
     embed_exif: bool,
 
+    /// 嵌入 exif 时去除 GPS 信息
+    #[arg(long)]
+    strip_gps: bool,
+
+    /// 不旋转像素，改为写入 exif 方向标签。添加边框或不嵌入 exif 时不起作用
+    #[arg(long)]
+    orientation_tag: bool,
+
     /// 边框字体，当指定该值时，则会添加边框
-    #[arg(short, long ,default_value = "")]
-    font_file: String,
+    #[arg(short, long)]
+    font_file: Option<String>,
+
+    /// 输出 16 位图像，仅 PNG 和 TIFF 支持
+    #[arg(long)]
+    sixteen_bit: bool,
+
+    /// 输出色彩空间（srgb、adobe、p3、prophoto、rec2020），输出图像会嵌入对应的 ICC 配置文件
+    #[arg(long, default_value = "srgb")]
+    color_space: String,
+
+    /// 输出格式（jpg、webp、png、tiff、avif、jxl），不指定时根据输出文件扩展名识别
+    #[arg(long)]
+    format: Option<String>,
 }
 
 lazy_static! {
@@ -130,6 +319,7 @@ async fn main() -> std::io::Result<()> {
                 let database = sub_matches.get_one::<String>("database").unwrap();
                 let index_path = sub_matches.get_one::<String>("index").unwrap();
                 let font_file = sub_matches.get_one::<String>("font_file").unwrap().to_string();
+                let lens_db = LensDb(sub_matches.get_one::<String>("lens_db").unwrap().to_string());
                 let bindaddr = format!("{}:{}",bind,port);
 
                 env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
@@ -150,6 +340,7 @@ async fn main() -> std::io::Result<()> {
                         .app_data(Data::new(pool.clone()))
                         .app_data(Data::new(index.clone()))
                         .app_data(Data::new(font_file.clone()))
+                        .app_data(Data::new(lens_db.clone()))
                         .configure(register)
                         .wrap(Cors::permissive())
                         .service(Files::new("/tmp", "./tmp"))
@@ -170,19 +361,171 @@ async fn main() -> std::io::Result<()> {
         Some(("convert",sub_matches)) => {
             let input = sub_matches.get_one::<String>("input").unwrap();
             let output = sub_matches.get_one::<String>("output").unwrap();
-            let lut = sub_matches.get_one::<String>("lut").unwrap();
+            let lut = sub_matches.get_one::<String>("lut");
+            let lut_intensity = sub_matches.get_one::<f32>("lut_intensity").unwrap();
+            let second_lut = sub_matches.get_one::<String>("second_lut");
+            let second_lut_intensity = sub_matches.get_one::<f32>("second_lut_intensity").unwrap();
+            let lut_interpolation = sub_matches.get_one::<String>("lut_interpolation").unwrap();
+            let lut_interpolation = LutInterpolation::from_name(lut_interpolation).ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("unknown lut interpolation: {}", lut_interpolation))
+            })?;
             let auto_wb = sub_matches.get_one::<bool>("auto_wb").unwrap();
+            let temperature = sub_matches.get_one::<f32>("temperature");
+            let tint = sub_matches.get_one::<f32>("tint").unwrap();
+            let wb_mul: Option<Vec<f32>> = sub_matches.get_many::<f32>("wb_mul").map(|v| v.copied().collect());
             let half_size = sub_matches.get_one::<bool>("half_size").unwrap();
-            let exp_shift = sub_matches.get_one::<f32>("exp_shift").unwrap();
-            let noise = sub_matches.get_one::<i32>("noise").unwrap();
-            let quality = sub_matches.get_one::<i32>("quality").unwrap();
+            let exp_shift = sub_matches.get_one::<f32>("exp_shift");
+            let noise = sub_matches.get_one::<f32>("noise");
+            let noise_reduction = NoiseReduction {
+                luminance: *sub_matches.get_one::<f32>("nr_luma").unwrap(),
+                chroma: *sub_matches.get_one::<f32>("nr_chroma").unwrap(),
+                fbdd: *sub_matches.get_one::<u8>("fbdd").unwrap(),
+                median_passes: *sub_matches.get_one::<u8>("median_passes").unwrap(),
+            };
+            let ca = sub_matches.get_many::<f32>("ca").map(|v| v.copied().collect::<Vec<f32>>());
+            let lens_correction = LensCorrection {
+                profile: *sub_matches.get_one::<bool>("lens_profile").unwrap(),
+                database: sub_matches.get_one::<String>("lens_db").unwrap().clone(),
+                vignetting: *sub_matches.get_one::<f32>("vignetting").unwrap(),
+                distortion: *sub_matches.get_one::<f32>("distortion").unwrap(),
+                ca_red: ca.as_ref().map_or(1.0, |ca| ca[0]),
+                ca_blue: ca.as_ref().map_or(1.0, |ca| ca[1]),
+            };
+            let sharpening = Sharpening {
+                amount: *sub_matches.get_one::<f32>("sharpen").unwrap(),
+                radius: *sub_matches.get_one::<f32>("sharpen_radius").unwrap(),
+                threshold: *sub_matches.get_one::<f32>("sharpen_threshold").unwrap(),
+                clarity: *sub_matches.get_one::<f32>("clarity").unwrap(),
+            };
+            let rotate = *sub_matches.get_one::<u16>("rotate").unwrap();
+            if !matches!(rotate, 0 | 90 | 180 | 270) {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("unknown rotation: {}", rotate)));
+            }
+            let crop = match sub_matches.get_one::<String>("crop") {
+                Some(crop) => Some(CropRect::parse(crop).ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("unknown crop: {}", crop))
+                })?),
+                None => None,
+            };
+            let aspect = sub_matches.get_one::<String>("aspect").unwrap();
+            let geometry = Geometry {
+                rotate: (rotate / 90) as u8,
+                straighten: *sub_matches.get_one::<f32>("straighten").unwrap(),
+                auto_crop: !*sub_matches.get_one::<bool>("no_auto_crop").unwrap(),
+                crop,
+                aspect: AspectRatio::from_name(aspect).ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("unknown aspect ratio: {}", aspect))
+                })?,
+            };
+            let quality = sub_matches.get_one::<u8>("quality").unwrap();
+            let highlight = sub_matches.get_one::<String>("highlight").unwrap();
+            let highlight = Highlight::from_name(highlight).ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("unknown highlight mode: {}", highlight))
+            })?;
+            let metering = sub_matches.get_one::<String>("metering").unwrap();
+            let metering = Metering::from_name(metering).ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("unknown metering mode: {}", metering))
+            })?;
+            let exp_preserve = sub_matches.get_one::<f32>("exp_preserve").unwrap();
+            let color_space = sub_matches.get_one::<String>("color_space").unwrap();
+            let color_space = ColorSpace::from_name(color_space).ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("unknown color space: {}", color_space))
+            })?;
+            let demosaic = sub_matches.get_one::<String>("demosaic").unwrap();
+            let demosaic = Demosaic::from_name(demosaic).ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("unknown demosaic algorithm: {}", demosaic))
+            })?;
+            let tone_map = sub_matches.get_one::<String>("tone_map").unwrap();
+            let tone_mapping = ToneMapping {
+                operator: ToneMapOperator::from_name(tone_map).ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("unknown tone map: {}", tone_map))
+                })?,
+                shift: *sub_matches.get_one::<f32>("tone_shift").unwrap(),
+                smooth: *sub_matches.get_one::<f32>("tone_smooth").unwrap(),
+            };
+            let adjust = |name: &str| *sub_matches.get_one::<f32>(name).unwrap();
+            let tone_curve = match sub_matches.get_one::<String>("tone_curve") {
+                Some(curve) => Adjustments::parse_tone_curve(curve).ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("unknown tone curve: {}", curve))
+                })?,
+                None => Vec::new(),
+            };
+            let adjustments = Adjustments {
+                contrast: adjust("contrast"),
+                highlights: adjust("highlights"),
+                shadows: adjust("shadows"),
+                whites: adjust("whites"),
+                blacks: adjust("blacks"),
+                saturation: adjust("saturation"),
+                vibrance: adjust("vibrance"),
+                tone_curve,
+            };
             let embed_exif = sub_matches.get_one::<bool>("embed_exif").unwrap();
-            let font_file = sub_matches.get_one::<String>("font_file").unwrap();
-
-            println!("{}",font_file);
-
-            let _ = raw_process(input.clone(),output.clone(),lut.clone(), *auto_wb, *half_size, *exp_shift, *noise,*quality,*embed_exif,font_file);
-            Ok(())
+            let strip_gps = sub_matches.get_one::<bool>("strip_gps").unwrap();
+            let orientation_tag = sub_matches.get_one::<bool>("orientation_tag").unwrap();
+            let font_file = sub_matches.get_one::<String>("font_file");
+            let sixteen_bit = sub_matches.get_one::<bool>("sixteen_bit").unwrap();
+            let format = match sub_matches.get_one::<String>("format") {
+                Some(format) => Some(OutputFormat::from_extension(format).ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidInput, RawError::UnknownFormat(format.clone()))
+                })?),
+                None => None,
+            };
+
+            let white_balance = match (wb_mul, temperature) {
+                (Some(mul), _) => WhiteBalance::Multipliers([mul[0], mul[1], mul[2], mul[3]]),
+                (None, Some(kelvin)) => WhiteBalance::Temperature { kelvin: *kelvin, tint: *tint },
+                (None, None) if *auto_wb => WhiteBalance::Auto,
+                (None, None) => WhiteBalance::Camera,
+            };
+
+            let mut options = ProcessOptions::builder()
+                .white_balance(white_balance)
+                .exposure(exp_shift.map_or(Exposure::Auto, |ev| Exposure::Manual(*ev)))
+                .metering(metering)
+                .denoise(noise.map_or(Denoise::Auto, |threshold| Denoise::Manual(*threshold)))
+                .noise_reduction(noise_reduction)
+                .lens_correction(lens_correction)
+                .half_size(*half_size)
+                .demosaic(demosaic)
+                .highlight(highlight)
+                .exp_preserve(*exp_preserve)
+                .bit_depth(if *sixteen_bit { BitDepth::Sixteen } else { BitDepth::Eight })
+                .color_space(color_space)
+                .tone_mapping(tone_mapping)
+                .adjustments(adjustments)
+                .sharpening(sharpening)
+                .quality(*quality)
+                .embed_exif(*embed_exif)
+                .strip_gps(*strip_gps)
+                .orientation(if *orientation_tag { Orientation::Tag } else { Orientation::Rotate });
+            // 未指定输出格式时按输出文件的扩展名选择
+            options = match format {
+                Some(format) => options.format(format),
+                None => options
+                    .format_from_path(output)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+            };
+            if let Some(lut) = lut {
+                options = options.lut(lut);
+            }
+            if let Some(second_lut) = second_lut {
+                options = options.second_lut(second_lut);
+            }
+            options = options
+                .lut_intensity(*lut_intensity)
+                .second_lut_intensity(*second_lut_intensity)
+                .lut_interpolation(lut_interpolation);
+            if let Some(font_file) = font_file {
+                options = options.frame(font_file);
+            }
+            if !geometry.is_identity() {
+                options = options.geometry(geometry);
+            }
+
+            raw_process(input, output, &options.build())
+                .map(|_| ())
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
         },
         _ => unreachable!("Exhausted list of subcommands and subcommand_required prevents `None`"),
     }
@@ -1,7 +1,10 @@
 use crate::db::{get_db_pool, sync_sqlite_to_tantivy, Pool};
 use crate::handlers::Parameters;
 use actix_web::web;
-use raw::raw_process;
+use raw::{
+    auto_exposure, clipping_mask, encode, process_to_image, raw_process, white_balance_multipliers,
+    Geometry, Orientation, ProcessOptions, RawError, WhiteBalance,
+};
 use raw::Myexif;
 use chrono::prelude::*;
 use blake2;
@@ -47,7 +50,7 @@ pub fn scan_directory(dir: &Path, filter_list: &[&str],base_dir:&Path) -> HashMa
     result
 }
 
-pub fn scan_files(user_id:i32,pool:Arc<Mutex<Pool>>){
+pub fn scan_files(user_id:i32,lens_db:&LensDb,pool:Arc<Mutex<Pool>>){
     let conn = pool.lock().unwrap().get().unwrap();
 
     let mut res = conn.prepare("select id,storage_name,storage_path from storages where user_id = :user_id and storage_type = :storage_type and storage_usage = 'source';").unwrap();
@@ -78,13 +81,13 @@ pub fn scan_files(user_id:i32,pool:Arc<Mutex<Pool>>){
             }
         }
     }
-    raw2img(user_id,pool);
+    raw2img(user_id,lens_db,pool);
     // let _index = index.lock().unwrap();
     // let _pool = pool.lock().unwrap().get().unwrap();
     
 }
 
-pub fn raw2(parames:web::Json<Parameters>,pool:Pool) -> Option<String>{
+fn original_file_path(parames:&Parameters,pool:&Pool) -> String{
     let db_conn = pool.get().unwrap();
     // println!("{:?}",parames);
     let original_path:String = db_conn.query_row("\
@@ -99,29 +102,171 @@ pub fn raw2(parames:web::Json<Parameters>,pool:Pool) -> Option<String>{
         where images.id = :id;"
         , named_params!{":id":&parames.id}, |row| row.get(0),).unwrap();
 
-        // let lut_path:String = db_conn.get().unwrap().query_row("",
-        let intput_file_path = format!("{}{}", original_path, parames.filename);
+    format!("{}{}", original_path, parames.filename)
+}
 
-        if let Ok(_) = fs::metadata(intput_file_path.clone()) {
-            let mut hasher = Blake2bVar::new(10).unwrap();
+/// 读取图像保存的旋转、拉直和裁切参数
+pub fn image_geometry(id:i32,pool:&Pool) -> Option<Geometry>{
+    let db_conn = pool.get().unwrap();
+    let geometry:Option<String> = db_conn.query_row("select geometry from images where id = :id;", named_params!{":id":&id}, |row| row.get(0)).ok()?;
+    geometry.and_then(|geometry| serde_json::from_str(&geometry).ok())
+}
 
-            let mut buf = [0u8; 10];
-            hasher.update(
-                format!(
-                    "{}{}{}{}{}{}",
-                    parames.id,
-                    parames.filename,
-                    parames.exp_shift,
-                    parames.lut,
-                    parames.threshold,
-                    parames.wb
-                )
-                .as_bytes(),
-            );
-            hasher.finalize_variable(&mut buf).unwrap();
+/// 保存图像的几何变换参数，并清除缓存图像，下次批量转换时按新参数重新生成
+pub fn save_geometry(id:i32,geometry:Option<&Geometry>,pool:&Pool) -> rusqlite::Result<usize>{
+    let db_conn = pool.get().unwrap();
+    let geometry = geometry.map(|geometry| serde_json::to_string(geometry).unwrap());
+    db_conn.execute(
+        "UPDATE images SET geometry = ?2, cache_id = NULL WHERE id = ?1",
+        (&id, &geometry),
+    )
+}
+
+/// 服务端的 lensfun 数据库路径，由 --lens-db 参数设置，转换时覆盖请求中的镜头校正参数
+#[derive(Clone)]
+pub struct LensDb(pub String);
+
+/// 根据编号查找用户上传的 lut 文件路径，编号不存在或 lut 不属于该用户时返回空
+fn lut_path(conn:&rusqlite::Connection,user_id:i32,lut_id:i32) -> Option<String>{
+    conn.query_row(
+        "select storages.storage_path || '/' || luts.lut_name from luts left join storages on luts.storage_id = storages.id where luts.id = :lut_id and storages.user_id = :user_id and storages.storage_usage = 'luts';",
+        named_params!{":lut_id":&lut_id,":user_id":&user_id},
+        |row| row.get(0),
+    ).ok()
+}
+
+/// 将参数中的 lut 编号解析为服务端的文件路径，不接受请求中直接指定的路径
+fn resolve_luts(options:&mut ProcessOptions,user_id:i32,pool:&Pool) -> Result<(),String>{
+    let conn = pool.get().unwrap();
+    let resolve = |lut_id:Option<i32>| match lut_id {
+        Some(lut_id) => lut_path(&conn, user_id, lut_id).map(Some).ok_or_else(|| format!("无效的 lut 编号 {}", lut_id)),
+        None => Ok(None),
+    };
+    options.lut = resolve(options.lut_id)?;
+    options.second_lut = resolve(options.second_lut_id)?;
+    Ok(())
+}
+
+// 预览固定使用半尺寸，不嵌入 exif 和相框，因此需要旋转像素
+// 请求中没有几何变换参数时使用图像保存的参数，请求参数未经过 builder，需要限制取值范围
+fn preview_options(parames:&Parameters,user_id:i32,lens_db:&LensDb,pool:&Pool) -> Option<ProcessOptions>{
+    let mut options = ProcessOptions {
+        half_size: true,
+        embed_exif: false,
+        orientation: Orientation::Rotate,
+        frame: None,
+        geometry: parames.options.geometry.or_else(|| image_geometry(parames.id, pool)),
+        ..parames.options.clone()
+    };
+    options.lens_correction.database = lens_db.0.clone();
+    options.sanitize();
+    if let Err(e) = resolve_luts(&mut options, user_id, pool) {
+        log::warn!("拒绝转换请求 {}: {}", parames.id, e);
+        return None;
+    }
+    Some(options)
+}
+
+/// 直接返回预览图像数据和对应的 Content-Type，不写入 ./tmp
+pub fn preview(parames:web::Json<Parameters>,user_id:i32,lens_db:&LensDb,pool:Pool) -> Option<(Vec<u8>,&'static str)>{
+    let intput_file_path = original_file_path(&parames, &pool);
+    let options = preview_options(&parames, user_id, lens_db, &pool)?;
+    let res = process_to_image(intput_file_path.as_str(), &options)
+        .and_then(|(img, _)| encode(&img, options.format, options.quality, options.bit_depth, options.color_space, None));
+    match res {
+        Ok(data) => Some((data, options.format.mime_type())),
+        Err(e) => {
+            log::error!("转换失败 {}: {}", intput_file_path, e);
+            None
+        }
+    }
+}
+
+/// 计算当前参数下的白平衡倍率，用于固定灰点取样的结果
+pub fn white_balance(parames:web::Json<Parameters>,user_id:i32,lens_db:&LensDb,pool:Pool) -> Option<[f32;4]>{
+    let intput_file_path = original_file_path(&parames, &pool);
+    match white_balance_multipliers(intput_file_path.as_str(), &preview_options(&parames, user_id, lens_db, &pool)?) {
+        Ok(mul) => Some(mul),
+        Err(e) => {
+            log::error!("计算白平衡失败 {}: {}", intput_file_path, e);
+            None
+        }
+    }
+}
+
+/// 计算当前参数下的自动曝光补偿（EV），供编辑器显示和手动调整
+pub fn exposure(parames:web::Json<Parameters>,user_id:i32,lens_db:&LensDb,pool:Pool) -> Option<f32>{
+    let intput_file_path = original_file_path(&parames, &pool);
+    match auto_exposure(intput_file_path.as_str(), &preview_options(&parames, user_id, lens_db, &pool)?) {
+        Ok(ev) => Some(ev),
+        Err(e) => {
+            log::error!("计算自动曝光失败 {}: {}", intput_file_path, e);
+            None
+        }
+    }
+}
+
+// 两个 lut 文件的修改时间，重新上传同名 lut 后缓存文件名随之变化
+fn lut_mtimes(options:&ProcessOptions) -> String{
+    [&options.lut, &options.second_lut]
+        .map(|lut| {
+            lut.as_deref()
+                .and_then(|lut| fs::metadata(lut).and_then(|m| m.modified()).ok())
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_nanos().to_string())
+                .unwrap_or_default()
+        })
+        .join(",")
+}
+
+// 预览文件名，由图像、转换参数和 lut 文件的修改时间决定
+fn preview_hash(parames:&Parameters,options:&ProcessOptions) -> String{
+    let mut hasher = Blake2bVar::new(10).unwrap();
+
+    let mut buf = [0u8; 10];
+    hasher.update(
+        format!(
+            "{}{}{}{}",
+            parames.id,
+            parames.filename,
+            serde_json::to_string(options).unwrap(),
+            lut_mtimes(options)
+        )
+        .as_bytes(),
+    );
+    hasher.finalize_variable(&mut buf).unwrap();
+    base16ct::lower::encode_string(&buf)
+}
+
+/// 生成预览图的溢出警告蒙版，返回 PNG 文件的地址
+pub fn clipping(parames:web::Json<Parameters>,user_id:i32,lens_db:&LensDb,pool:Pool) -> Option<String>{
+    let intput_file_path = original_file_path(&parames, &pool);
+    let options = preview_options(&parames, user_id, lens_db, &pool)?;
+    let out_file_name = format!("{}_clip.png", preview_hash(&parames, &options));
+    let out_file_path = format!("./tmp/{}", out_file_name);
+
+    if fs::metadata(&out_file_path).is_ok() {
+        return Some(format!("/tmp/{}",out_file_name));
+    }
+    let res = process_to_image(intput_file_path.as_str(), &options)
+        .and_then(|(img, _)| clipping_mask(&img).save(&out_file_path).map_err(|e| RawError::Encode(e.to_string())));
+    match res {
+        Ok(_) => Some(format!("/tmp/{}",out_file_name)),
+        Err(e) => {
+            log::error!("生成溢出蒙版失败 {}: {}", intput_file_path, e);
+            None
+        }
+    }
+}
+
+pub fn raw2(parames:web::Json<Parameters>,user_id:i32,lens_db:&LensDb,pool:Pool) -> Option<String>{
+        let intput_file_path = original_file_path(&parames, &pool);
+
+        if let Ok(_) = fs::metadata(intput_file_path.clone()) {
+            let options = preview_options(&parames, user_id, lens_db, &pool)?;
 
             // let _ = std::fs::create_dir_all(format!("./tmp/", dir_path));
-            let out_file_name = format!("{}.jpg", base16ct::lower::encode_string(&buf));
+            let out_file_name = format!("{}.{}", preview_hash(&parames, &options), options.format.extension());
             let out_file_path = format!(
                 "./tmp/{}",
                 out_file_name
@@ -130,19 +275,13 @@ pub fn raw2(parames:web::Json<Parameters>,pool:Pool) -> Option<String>{
             if let Ok(_) = fs::metadata(out_file_path.clone()) {
                 Some(format!("/tmp/{}",out_file_name))
             } else {
-                let _ = raw_process(
-                    intput_file_path,
-                    out_file_path.clone(),
-                    parames.lut.clone(),
-                    parames.wb,
-                    true,
-                    parames.exp_shift as f32,
-                    parames.threshold,
-                    90,
-                    false,
-                    "",
-                );
-                Some(format!("/tmp/{}",out_file_name))
+                match raw_process(&intput_file_path, &out_file_path, &options) {
+                    Ok(_) => Some(format!("/tmp/{}",out_file_name)),
+                    Err(e) => {
+                        log::error!("转换失败 {}: {}", intput_file_path, e);
+                        None
+                    }
+                }
             }
         }
         else{
@@ -150,23 +289,37 @@ pub fn raw2(parames:web::Json<Parameters>,pool:Pool) -> Option<String>{
         }
 }
 
-pub fn raw2img(user_id:i32,pool:Arc<Mutex<Pool>>){
+pub fn raw2img(user_id:i32,lens_db:&LensDb,pool:Arc<Mutex<Pool>>){
     let conn = pool.lock().unwrap().get().unwrap();
-    let mut res = conn.prepare("select images.id,file_name,mime_type,scan_time,storages.storage_path || paths.path || '/' || images.file_name as file_path from images left join paths on images.path_id = paths.id left join storages on paths.storage_id = storages.id where images.user_id = :user_id and storages.storage_type = :storage_type and images.cache_id is null;").unwrap();
-    let images:Vec<(i32,String,String,String,String)> = res.query_map(named_params!{":user_id":&user_id,":storage_type":"local"},|row| {
-        Ok((row.get(0).unwrap(),row.get(1).unwrap(),row.get(2).unwrap(),row.get(3).unwrap(),row.get(4).unwrap()
+    let mut res = conn.prepare("select images.id,file_name,mime_type,scan_time,storages.storage_path || paths.path || '/' || images.file_name as file_path,images.geometry from images left join paths on images.path_id = paths.id left join storages on paths.storage_id = storages.id where images.user_id = :user_id and storages.storage_type = :storage_type and images.cache_id is null;").unwrap();
+    let images:Vec<(i32,String,String,String,String,Option<String>)> = res.query_map(named_params!{":user_id":&user_id,":storage_type":"local"},|row| {
+        Ok((row.get(0).unwrap(),row.get(1).unwrap(),row.get(2).unwrap(),row.get(3).unwrap(),row.get(4).unwrap(),row.get(5).unwrap()
         ))
     }).unwrap().into_iter().filter_map(Result::ok).collect();
     // println!("{:?}",images);
-    let (lut_name,lut_path) = match conn.query_row("select lut_name,storage_path || '/' || lut_name from users left join luts on users.lut_id = luts.id left join storages on luts.storage_id = storages.id where users.id = :user_id;", named_params!{":user_id":&user_id}, |row| Ok((row.get(0).unwrap(),row.get(1).unwrap())),){
-        Ok((_lut_name,_lut_path)) => (_lut_name,_lut_path),
-        Err(_) => ("".to_string(),"".to_string())
-    };
-
-    let (wb,half_size,quality) = match conn.query_row("select wb,half_size,quality where users.id = :user_id;", named_params!{":user_id":&user_id}, |row| Ok((row.get(0).unwrap(),row.get(1).unwrap(),row.get(2).unwrap())),){
-        Ok((_wb,_half_size,_quality)) => (_wb,_half_size,_quality),
-        Err(_) => (true,true,90)
+    let (wb,half_size,quality,lut_id,options):(bool,bool,u8,Option<i32>,String) = match conn.query_row("select wb,half_size,quality,lut_id,options from users where users.id = :user_id;", named_params!{":user_id":&user_id}, |row| Ok((row.get(0).unwrap(),row.get(1).unwrap(),row.get(2).unwrap(),row.get(3).unwrap_or_default(),row.get(4).unwrap_or("".to_string()))),){
+        Ok((_wb,_half_size,_quality,_lut_id,_options)) => (_wb,_half_size,_quality,_lut_id,_options),
+        Err(_) => (true,true,90,None,"".to_string())
     };
+    // 用户设置中的白平衡、尺寸、质量和 lut 覆盖 options 中的同名参数，options 中保存了手动白平衡时优先使用
+    let mut options: ProcessOptions = serde_json::from_str(&options).unwrap_or_default();
+    if matches!(options.white_balance, WhiteBalance::Auto | WhiteBalance::Camera) {
+        options.white_balance = if wb { WhiteBalance::Auto } else { WhiteBalance::Camera };
+    }
+    options.half_size = half_size;
+    options.quality = quality;
+    options.embed_exif = false;
+    options.orientation = Orientation::Rotate;
+    options.frame = None;
+    options.lens_correction.database = lens_db.0.clone();
+    // lut 强度和叠加的第二个 lut 使用 options 中保存的值
+    // 设置页面中不使用 lut 时 lut_id 为 -1
+    options.lut_id = lut_id.filter(|&id| id > 0);
+    if let Err(e) = resolve_luts(&mut options, user_id, &pool.lock().unwrap()) {
+        log::error!("批量转换失败 {}: {}", user_id, e);
+        return;
+    }
+    let lut_mtimes = lut_mtimes(&options);
 
     let (storage_id,storage_path):(i32,String) = conn.query_row("select id,storage_path from storages where user_id = :user_id and storage_usage = 'cache';", named_params!{":user_id":&user_id}, |row| Ok((row.get(0).unwrap(),row.get(1).unwrap())),).unwrap();
     
@@ -188,46 +341,51 @@ pub fn raw2img(user_id:i32,pool:Arc<Mutex<Pool>>){
     
     let cache_id:i32 = conn.query_row("select id from paths where path = :path and storage_id= :storage_id;", named_params!{":path":&_cache_path,":storage_id":&storage_id}, |row| row.get(0)).unwrap();
     // println!("{:?}",cache_id);
-    for (_id,_file_name,_type,_scan_time,_path) in images{
+    for (_id,_file_name,_type,_scan_time,_path,_geometry) in images{
         // println!("{}",_path);
         if let Ok(_) = fs::metadata(_path.clone()) {
+            // 几何变换参数按图像保存，不使用用户默认设置中的值
+            let _geometry = _geometry.unwrap_or_default();
+            options.geometry = serde_json::from_str(&_geometry).ok();
+            options.sanitize();
+            // 缓存文件名由图像、完整的转换参数和 lut 文件的修改时间决定，任一参数变化都会重新生成
             let mut hasher = Blake2bVar::new(10).unwrap();
 
             let mut buf = [0u8; 10];
             hasher.update(
                 format!(
-                    "{}{}{}{}{}",
+                    "{}{}{}{}{}{}",
                     _id,
                     _file_name,
                     _type,
                     _scan_time,
-                    lut_name
+                    serde_json::to_string(&options).unwrap(),
+                    lut_mtimes
                 )
                 .as_bytes(),
             );
             hasher.finalize_variable(&mut buf).unwrap();
             
-            let out_file_name = format!("{}.jpg",base16ct::lower::encode_string(&buf));
+            let out_file_name = format!("{}.{}",base16ct::lower::encode_string(&buf),options.format.extension());
             let out_file_path = format!("{}/{}",cache_path,out_file_name);
             // println!("{} {}",out_file_name,out_file_path);
-            if let Ok(_exif) = raw_process(
-                _path,
-                out_file_path,
-                lut_path.clone(),
-                wb,
-                half_size,
-                -3.0,
-                -3,
-                quality,
-                false,
-                ""
-            ){
-                // let s = _exif.shooting_date;
-                let _exif_json = serde_json::to_string(&_exif).unwrap();
-                conn.execute(
-                    "UPDATE images SET cache_id = ?2,cache_file_name = ?3, exif = ?4, shooting_time= ?5 WHERE id = ?1",
-                    (&_id, &cache_id,&out_file_name,&_exif_json,&_exif.shooting_date),
-                ).unwrap();
+            // 参数未变化时缓存文件已存在，只需重新关联
+            if fs::metadata(&out_file_path).is_ok() && conn.execute(
+                "UPDATE images SET cache_id = ?2,cache_file_name = ?3 WHERE id = ?1 and exif is not null",
+                (&_id, &cache_id,&out_file_name),
+            ).unwrap() > 0 {
+                continue;
+            }
+            match raw_process(&_path, &out_file_path, &options){
+                Ok(_exif) => {
+                    // let s = _exif.shooting_date;
+                    let _exif_json = serde_json::to_string(&_exif).unwrap();
+                    conn.execute(
+                        "UPDATE images SET cache_id = ?2,cache_file_name = ?3, exif = ?4, shooting_time= ?5 WHERE id = ?1",
+                        (&_id, &cache_id,&out_file_name,&_exif_json,&_exif.shooting_date),
+                    ).unwrap();
+                }
+                Err(e) => log::error!("转换失败 {}: {}", _path, e),
             }
             
         }
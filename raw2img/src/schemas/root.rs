@@ -244,11 +244,13 @@ impl MutationRoot {
         let conn = context.db_pool.get().unwrap();
         
         let res = conn.execute(
-            "UPDATE users SET wb = ?2, half_size = ?3, quality = ?4, lut_id = ?5 where id = ?1",
-            (&id,&user.wb,&user.half_size,&user.quality,&user.lut_id),
+            "UPDATE users SET wb = ?2, half_size = ?3, quality = ?4, lut_id = ?5, options = coalesce(?6, options) where id = ?1",
+            (&id,&user.wb,&user.half_size,&user.quality,&user.lut_id,&user.options),
         );
         match res {
             Ok(_) =>{
+                // 转换参数可能已变化，下次批量转换时按缓存文件名判断是否需要重新生成
+                let _ = conn.execute("UPDATE images SET cache_id = NULL WHERE user_id = ?1", (&id,));
                 // let _id = conn.last_insert_rowid();
                 Ok(
                     User{
@@ -259,6 +261,7 @@ impl MutationRoot {
                         half_size: user.half_size,
                         quality: user.quality,
                         lut_id: user.lut_id,
+                        options: user.options.unwrap_or_default(),
                     }
                 )
             }
@@ -67,6 +67,7 @@ impl Storage {
                 half_size: row.get(5).unwrap(),
                 quality: row.get(6).unwrap(),
                 lut_id: row.get(7).unwrap_or(-1),
+                options: row.get(8).unwrap_or("".to_string()),
             })
         });
         if let Err(_err) = res{
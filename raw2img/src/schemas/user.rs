@@ -1,6 +1,6 @@
 use chrono::Local;
 use juniper::{graphql_object, GraphQLInputObject};
-use raw::Myexif;
+use raw::{GpsInfo, Myexif};
 use tantivy::{collector::TopDocs, query::QueryParser, schema::Value, TantivyDocument};
 use crate::schemas::{root::Context,image::Image,image::row2img,storage::Storage,storage::row2storage};
 use rusqlite::Error;
@@ -14,6 +14,7 @@ pub struct User {
     pub wb: bool,
     pub half_size: bool,
     pub quality: i32,
+    pub options: String,
 }
 
 #[derive(GraphQLInputObject)]
@@ -26,6 +27,7 @@ pub struct UserInput {
     pub wb: bool,
     pub half_size: bool,
     pub quality: i32,
+    pub options: Option<String>,
 }
 
 #[graphql_object(Context = Context)]
@@ -51,6 +53,9 @@ impl User {
     fn quality(&self) -> &i32{
         &self.quality
     }
+    fn options(&self) -> &str{
+        &self.options
+    }
 
     fn storages(&self, context: &Context) -> Vec<Storage> {
         let conn = context.db_pool.get().unwrap();
@@ -82,8 +87,19 @@ impl User {
         let user_id = schema.get_field("user_id").unwrap();
         let cache_url = schema.get_field("cache_url").unwrap();
         let shooting_date = schema.get_field("shooting_date").unwrap();
-
-        let mut query_parser = QueryParser::for_index(&context.index, vec![file_name]);
+        let make = schema.get_field("make").unwrap();
+        let model = schema.get_field("model").unwrap();
+        let lens = schema.get_field("lens").unwrap();
+        let focal_length = schema.get_field("focal_length").unwrap();
+        let exposure_bias = schema.get_field("exposure_bias").unwrap();
+        let latitude = schema.get_field("latitude").unwrap();
+        let longitude = schema.get_field("longitude").unwrap();
+        let altitude = schema.get_field("altitude").unwrap();
+        let orientation = schema.get_field("orientation").unwrap();
+        let width = schema.get_field("width").unwrap();
+        let height = schema.get_field("height").unwrap();
+
+        let mut query_parser = QueryParser::for_index(&context.index, vec![file_name, make, model, lens]);
         // query_parser.set_field_fuzzy(file_name,false,2,true);
         
         let query_str = format!("user_id:{0} AND ({1})",self.id,query);
@@ -102,12 +118,31 @@ impl User {
             let _time:chrono::DateTime<Local> = chrono::DateTime::from(chrono::DateTime::from_timestamp_millis(_time_timestamp).unwrap());
             let _time_str = _time.format("%Y-%m-%d %H:%M:%S").to_string();
 
+            let text = |field| retrieved_doc.get_first(field).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+            let float = |field| retrieved_doc.get_first(field).and_then(|v| v.as_f64());
+            let int = |field| retrieved_doc.get_first(field).and_then(|v| v.as_i64()).unwrap_or_default();
+            let gps = float(latitude).zip(float(longitude)).map(|(latitude, longitude)| GpsInfo {
+                latitude,
+                longitude,
+                altitude: float(altitude).unwrap_or_default() as f32,
+            });
+
             let _exif:Myexif = Myexif{
                 iso:retrieved_doc.get_first(iso).unwrap().as_f64().unwrap() as f32,
                 aperture:retrieved_doc.get_first(aperture).unwrap().as_f64().unwrap() as f32,
                 shutter:retrieved_doc.get_first(shutter).unwrap().as_f64().unwrap() as f32,
                 focal_len:retrieved_doc.get_first(focal_len).unwrap().as_i64().unwrap() as u16,
                 shooting_date:_time_str.clone(),
+                make:text(make),
+                model:text(model),
+                lens:text(lens),
+                focal_length:float(focal_length).unwrap_or_default() as f32,
+                exposure_bias:float(exposure_bias).unwrap_or_default() as f32,
+                gps,
+                orientation:int(orientation) as u16,
+                width:int(width) as u32,
+                height:int(height) as u32,
+                ..Default::default()
             };
 
             let _exif_str = serde_json::to_string(&_exif).unwrap();
@@ -140,5 +175,6 @@ pub fn row2user(row:&rusqlite::Row<'_>) -> Result<User, Error>{
         half_size: row.get(5).unwrap(),
         quality: row.get(6).unwrap(),
         lut_id: row.get(7).unwrap_or(-1),
+        options: row.get(8).unwrap_or("".to_string()),
     })
 }
\ No newline at end of file
@@ -9,6 +9,7 @@ use graphql_client::{reqwest::post_graphql, GraphQLQuery};
 
 use console_error_panic_hook;
 
+mod options;
 mod pages;
 
 use pages::{home, login, setting};
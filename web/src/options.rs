@@ -0,0 +1,427 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+// 与 raw::ProcessOptions 保持一致的转换参数，web 端无法依赖 raw 库
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WhiteBalance {
+    Auto,
+    #[default]
+    Camera,
+    Temperature { kelvin: f32, tint: f32 },
+    Multipliers([f32; 4]),
+    GreyPoint { x: f32, y: f32 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Exposure {
+    #[default]
+    Auto,
+    Manual(f32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Denoise {
+    #[default]
+    Auto,
+    Manual(f32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Highlight {
+    #[default]
+    Clip,
+    Unclip,
+    Blend,
+    Rebuild(u8),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Demosaic {
+    Linear,
+    Vng,
+    Ppg,
+    #[default]
+    Ahd,
+    Dcb,
+    Dht,
+    Aahd,
+}
+
+impl Demosaic {
+    pub const ALL: [Demosaic; 7] = [
+        Demosaic::Linear,
+        Demosaic::Vng,
+        Demosaic::Ppg,
+        Demosaic::Ahd,
+        Demosaic::Dcb,
+        Demosaic::Dht,
+        Demosaic::Aahd,
+    ];
+
+    /// 序列化后的名称，用作 select 的 value
+    pub fn name(&self) -> &'static str {
+        match self {
+            Demosaic::Linear => "linear",
+            Demosaic::Vng => "vng",
+            Demosaic::Ppg => "ppg",
+            Demosaic::Ahd => "ahd",
+            Demosaic::Dcb => "dcb",
+            Demosaic::Dht => "dht",
+            Demosaic::Aahd => "aahd",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Demosaic::Linear => "线性（最快）",
+            Demosaic::Vng => "VNG",
+            Demosaic::Ppg => "PPG",
+            Demosaic::Ahd => "AHD",
+            Demosaic::Dcb => "DCB",
+            Demosaic::Dht => "DHT",
+            Demosaic::Aahd => "AAHD",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LutInterpolation {
+    Nearest,
+    Trilinear,
+    #[default]
+    Tetrahedral,
+}
+
+impl LutInterpolation {
+    pub const ALL: [LutInterpolation; 3] = [
+        LutInterpolation::Nearest,
+        LutInterpolation::Trilinear,
+        LutInterpolation::Tetrahedral,
+    ];
+
+    /// 序列化后的名称，用作 select 的 value
+    pub fn name(&self) -> &'static str {
+        match self {
+            LutInterpolation::Nearest => "nearest",
+            LutInterpolation::Trilinear => "trilinear",
+            LutInterpolation::Tetrahedral => "tetrahedral",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LutInterpolation::Nearest => "最近点（最快）",
+            LutInterpolation::Trilinear => "三线性",
+            LutInterpolation::Tetrahedral => "四面体",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorSpace {
+    #[default]
+    Srgb,
+    AdobeRgb,
+    DisplayP3,
+    ProPhoto,
+    Rec2020Linear,
+}
+
+impl ColorSpace {
+    pub const ALL: [ColorSpace; 5] = [
+        ColorSpace::Srgb,
+        ColorSpace::AdobeRgb,
+        ColorSpace::DisplayP3,
+        ColorSpace::ProPhoto,
+        ColorSpace::Rec2020Linear,
+    ];
+
+    /// 序列化后的名称，用作 select 的 value
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "srgb",
+            ColorSpace::AdobeRgb => "adobe_rgb",
+            ColorSpace::DisplayP3 => "display_p3",
+            ColorSpace::ProPhoto => "pro_photo",
+            ColorSpace::Rec2020Linear => "rec2020_linear",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "sRGB",
+            ColorSpace::AdobeRgb => "Adobe RGB",
+            ColorSpace::DisplayP3 => "Display P3",
+            ColorSpace::ProPhoto => "ProPhoto RGB",
+            ColorSpace::Rec2020Linear => "线性 Rec.2020",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    #[default]
+    Jpeg,
+    Webp,
+    Png,
+    Tiff,
+    Avif,
+    Jxl,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 6] = [
+        OutputFormat::Jpeg,
+        OutputFormat::Webp,
+        OutputFormat::Png,
+        OutputFormat::Tiff,
+        OutputFormat::Avif,
+        OutputFormat::Jxl,
+    ];
+
+    /// 序列化后的名称，用作 select 的 value
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "jpeg",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Png => "png",
+            OutputFormat::Tiff => "tiff",
+            OutputFormat::Avif => "avif",
+            OutputFormat::Jxl => "jxl",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "JPEG",
+            OutputFormat::Webp => "WebP",
+            OutputFormat::Png => "PNG",
+            OutputFormat::Tiff => "TIFF",
+            OutputFormat::Avif => "AVIF",
+            OutputFormat::Jxl => "JPEG XL",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Orientation {
+    #[default]
+    Rotate,
+    Tag,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMapOperator {
+    #[default]
+    None,
+    Gamma,
+    Linear,
+    Filmic,
+    Aces,
+    Reinhard,
+}
+
+impl ToneMapOperator {
+    pub const ALL: [ToneMapOperator; 6] = [
+        ToneMapOperator::None,
+        ToneMapOperator::Gamma,
+        ToneMapOperator::Linear,
+        ToneMapOperator::Filmic,
+        ToneMapOperator::Aces,
+        ToneMapOperator::Reinhard,
+    ];
+
+    /// 序列化后的名称，用作 select 的 value
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapOperator::None => "none",
+            ToneMapOperator::Gamma => "gamma",
+            ToneMapOperator::Linear => "linear",
+            ToneMapOperator::Filmic => "filmic",
+            ToneMapOperator::Aces => "aces",
+            ToneMapOperator::Reinhard => "reinhard",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ToneMapOperator::None => "不使用",
+            ToneMapOperator::Gamma => "Gamma",
+            ToneMapOperator::Linear => "线性（高光平滑）",
+            ToneMapOperator::Filmic => "Filmic",
+            ToneMapOperator::Aces => "ACES",
+            ToneMapOperator::Reinhard => "Reinhard",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    pub shift: f32,
+    pub smooth: f32,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping {
+            operator: ToneMapOperator::default(),
+            shift: 1.0,
+            smooth: 0.9,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Sharpening {
+    pub amount: f32,
+    pub radius: f32,
+    pub threshold: f32,
+    pub clarity: f32,
+}
+
+impl Default for Sharpening {
+    fn default() -> Self {
+        Sharpening {
+            amount: 0.0,
+            radius: 1.0,
+            threshold: 0.0,
+            clarity: 0.0,
+        }
+    }
+}
+
+/// 不包含数据库路径，由服务端使用默认值
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct LensCorrection {
+    pub profile: bool,
+    pub vignetting: f32,
+    pub distortion: f32,
+    pub ca_red: f32,
+    pub ca_blue: f32,
+}
+
+impl Default for LensCorrection {
+    fn default() -> Self {
+        LensCorrection {
+            profile: false,
+            vignetting: 0.0,
+            distortion: 0.0,
+            ca_red: 1.0,
+            ca_blue: 1.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AspectRatio {
+    #[default]
+    Free,
+    Original,
+    Square,
+    Ratio3x2,
+    Ratio4x3,
+    Ratio5x4,
+    Ratio16x9,
+}
+
+impl AspectRatio {
+    pub const ALL: [AspectRatio; 7] = [
+        AspectRatio::Free,
+        AspectRatio::Original,
+        AspectRatio::Square,
+        AspectRatio::Ratio3x2,
+        AspectRatio::Ratio4x3,
+        AspectRatio::Ratio5x4,
+        AspectRatio::Ratio16x9,
+    ];
+
+    /// 序列化后的名称，用作 select 的 value
+    pub fn name(&self) -> &'static str {
+        match self {
+            AspectRatio::Free => "free",
+            AspectRatio::Original => "original",
+            AspectRatio::Square => "square",
+            AspectRatio::Ratio3x2 => "ratio3x2",
+            AspectRatio::Ratio4x3 => "ratio4x3",
+            AspectRatio::Ratio5x4 => "ratio5x4",
+            AspectRatio::Ratio16x9 => "ratio16x9",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AspectRatio::Free => "自由",
+            AspectRatio::Original => "原始比例",
+            AspectRatio::Square => "1:1",
+            AspectRatio::Ratio3x2 => "3:2",
+            AspectRatio::Ratio4x3 => "4:3",
+            AspectRatio::Ratio5x4 => "5:4",
+            AspectRatio::Ratio16x9 => "16:9",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CropRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Geometry {
+    pub rotate: u8,
+    pub straighten: f32,
+    pub auto_crop: bool,
+    pub crop: Option<CropRect>,
+    pub aspect: AspectRatio,
+}
+
+impl Default for Geometry {
+    fn default() -> Self {
+        Geometry {
+            rotate: 0,
+            straighten: 0.0,
+            auto_crop: true,
+            crop: None,
+            aspect: AspectRatio::Free,
+        }
+    }
+}
+
+/// 从用户默认参数 JSON 中读取某个字段，缺失或无法解析时返回默认值
+pub fn get_option<T: DeserializeOwned + Default>(options: &str, key: &str) -> T {
+    serde_json::from_str::<Value>(options)
+        .ok()
+        .and_then(|v| v.get(key).cloned())
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+/// 修改用户默认参数 JSON 中的某个字段，保留其它字段
+pub fn set_option<T: Serialize>(options: &str, key: &str, value: T) -> String {
+    let mut map = match serde_json::from_str::<Value>(options) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    };
+    map.insert(key.to_string(), serde_json::to_value(value).unwrap_or_default());
+    Value::Object(map).to_string()
+}
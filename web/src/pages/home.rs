@@ -3,18 +3,34 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use sycamore::{futures::spawn_local_scoped, web::html::img};
 use sycamore::prelude::*;
-use web_sys::{HtmlInputElement, HtmlOptionElement};
+use web_sys::{HtmlElement, HtmlInputElement, HtmlOptionElement, MouseEvent};
 use graphql_client::{reqwest::post_graphql, GraphQLQuery};
 
+use crate::options::{
+    set_option, AspectRatio, CropRect, Demosaic, Denoise, Exposure, Geometry, Highlight,
+    LensCorrection, LutInterpolation, Sharpening, WhiteBalance,
+};
+use crate::pages::setting::{getuser, updateuser};
+
 
 #[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
 struct Parameters {
     id:i32,
     filename: String,
-    lut: String,
-    wb: bool,
-    exp_shift: f64,
-    threshold: i32,
+    lut_id: Option<i32>,
+    lut_intensity: f32,
+    second_lut_id: Option<i32>,
+    second_lut_intensity: f32,
+    lut_interpolation: LutInterpolation,
+    white_balance: WhiteBalance,
+    exposure: Exposure,
+    denoise: Denoise,
+    demosaic: Demosaic,
+    highlight: Highlight,
+    exp_preserve: f32,
+    lens_correction: LensCorrection,
+    sharpening: Sharpening,
+    geometry: Option<Geometry>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -86,7 +102,7 @@ async fn getluts(url:&str) -> Vec<(String, String)> {
         post_graphql::<LutsQuery, _>(&client, url, variables).await.unwrap();
     // log::info!("{:?}",response_body);
     let response_data: luts_query::ResponseData = response_body.data.expect("missing response data");
-    response_data.luts.iter().map(|x| (format!("{}/{}",x.path.clone(),x.lut_name.clone()),x.lut_name.clone())).collect()
+    response_data.luts.iter().map(|x| (x.id.to_string(),x.lut_name.clone())).collect()
 }
 
 async fn getrawfiles(user_id:i32, url:&str) -> (Vec<Image>,Vec<(String, Vec<(usize, Image)>)>) {
@@ -255,6 +271,71 @@ async fn get_jpg(params: Parameters,base_url:&str) -> String {
     // format!("{}/api/{}", base_url, body)
 }
 
+/// 获取预览图的溢出警告蒙版地址
+async fn get_clipping(params: Parameters,base_url:&str) -> Option<String> {
+    let url = format!("{}/api/clipping", base_url);
+    reqwest::Client::new()
+        .post(&url)
+        .json(&params)
+        .send()
+        .await
+        .ok()?
+        .json()
+        .await
+        .ok()
+}
+
+/// 以灰点取样计算白平衡倍率
+async fn get_wb(params: Parameters,base_url:&str) -> Option<[f32;4]> {
+    let url = format!("{}/api/wb", base_url);
+    reqwest::Client::new()
+        .post(&url)
+        .json(&params)
+        .send()
+        .await
+        .ok()?
+        .json()
+        .await
+        .ok()
+}
+
+/// 计算当前参数下的自动曝光补偿（EV）
+async fn get_exposure(params: Parameters,base_url:&str) -> Option<f32> {
+    let url = format!("{}/api/exposure", base_url);
+    reqwest::Client::new()
+        .post(&url)
+        .json(&params)
+        .send()
+        .await
+        .ok()?
+        .json()
+        .await
+        .ok()
+}
+
+/// 读取图像保存的旋转、拉直和裁切参数
+async fn get_geometry(image_id: i32, base_url:&str) -> Option<Geometry> {
+    let url = format!("{}/api/geometry/{}", base_url, image_id);
+    reqwest::Client::new()
+        .get(&url)
+        .send()
+        .await
+        .ok()?
+        .json()
+        .await
+        .ok()?
+}
+
+/// 保存图像的旋转、拉直和裁切参数，批量转换时按此参数重新生成
+async fn save_geometry(image_id: i32, geometry: Geometry, base_url:&str) {
+    let url = format!("{}/api/geometry", base_url);
+    let _ = reqwest::Client::new()
+        .post(&url)
+        .json(&(image_id, Some(geometry)))
+        .send()
+        .await;
+}
+
 async fn save_jpg(url_file: String, image_id: i32, base_url:&str) {
     // let base_url = web_sys::window().unwrap().location().origin().unwrap();
     // let url = format!("http://127.0.0.1:8081/api/save");
@@ -283,6 +364,8 @@ pub async fn Body<G: Html>(cx: Scope<'_>) -> View<G> {
     let exp_shift_flag = create_signal(cx, true);
     let exp_shift_ref = create_node_ref(cx);
     exp_shift.set("0".to_string());
+    // 最近一次测光得到的自动曝光补偿，可作为手动曝光的起点
+    let auto_ev = create_signal(cx, None::<f32>);
 
     let threshold = create_signal(cx, String::new());
     let threshold_flag = create_signal(cx, true);
@@ -290,6 +373,12 @@ pub async fn Body<G: Html>(cx: Scope<'_>) -> View<G> {
     threshold.set("0".to_string());
 
     let lut_ref = create_node_ref(cx);
+    // lut 强度（%）和叠加在第一个 lut 之后的第二个 lut
+    let lut_intensity = create_signal(cx, "100".to_string());
+    let second_lut_ref = create_node_ref(cx);
+    let second_lut_intensity = create_signal(cx, "100".to_string());
+    let lut_interpolation = create_signal(cx, LutInterpolation::default().name().to_string());
+    let lut_interpolations = create_signal(cx, LutInterpolation::ALL.to_vec());
 
     let images = create_signal(cx, Vec::new());
 
@@ -310,12 +399,99 @@ pub async fn Body<G: Html>(cx: Scope<'_>) -> View<G> {
 
     
 
-    let auto_wb_ref = create_node_ref(cx);
-    let wb_label = create_signal(cx, String::new());
+    // 白平衡模式：auto、camera、temperature、multipliers
+    let wb_mode = create_signal(cx, "auto".to_string());
+    let kelvin = create_signal(cx, "5500".to_string());
+    let tint = create_signal(cx, "0".to_string());
+    let wb_mul = create_signal(cx, [1.0f32; 4]);
+    // 是否正在预览图上选取灰点
+    let picking = create_signal(cx, false);
+    let preview_ref = create_node_ref(cx);
+
+    let white_balance = move || match wb_mode.get().as_str() {
+        "auto" => WhiteBalance::Auto,
+        "temperature" => WhiteBalance::Temperature {
+            kelvin: kelvin.get().parse().unwrap_or(5500.0),
+            tint: tint.get().parse().unwrap_or(0.0),
+        },
+        "multipliers" => WhiteBalance::Multipliers(*wb_mul.get()),
+        _ => WhiteBalance::Camera,
+    };
 
-    let loading = create_signal(cx, false);
+    let demosaic = create_signal(cx, Demosaic::default().name().to_string());
+    let demosaics = create_signal(cx, Demosaic::ALL.to_vec());
+
+    // 高光恢复模式：clip、unclip、blend、rebuild
+    let highlight = create_signal(cx, "clip".to_string());
+    let rebuild_level = create_signal(cx, "5".to_string());
+    let exp_preserve = create_signal(cx, "0.8".to_string());
+    let clipping_flag = create_signal(cx, false);
+    let lens_profile = create_signal(cx, false);
+    let vignetting = create_signal(cx, "0".to_string());
+    let distortion = create_signal(cx, "0".to_string());
+    let ca_red = create_signal(cx, "1".to_string());
+    let ca_blue = create_signal(cx, "1".to_string());
+    let lens_correction = move || LensCorrection {
+        profile: *lens_profile.get(),
+        vignetting: vignetting.get().parse().unwrap_or(0.0),
+        distortion: distortion.get().parse().unwrap_or(0.0),
+        ca_red: ca_red.get().parse().unwrap_or(1.0),
+        ca_blue: ca_blue.get().parse().unwrap_or(1.0),
+    };
+    let sharpen_amount = create_signal(cx, "0".to_string());
+    let sharpen_radius = create_signal(cx, "1".to_string());
+    let sharpen_threshold = create_signal(cx, "0".to_string());
+    let clarity = create_signal(cx, "0".to_string());
+    let sharpening = move || Sharpening {
+        amount: sharpen_amount.get().parse().unwrap_or(0.0),
+        radius: sharpen_radius.get().parse().unwrap_or(1.0),
+        threshold: sharpen_threshold.get().parse().unwrap_or(0.0),
+        clarity: clarity.get().parse().unwrap_or(0.0),
+    };
+    // 旋转、拉直和裁切，裁切以四边向内裁掉的百分比表示
+    let rotate = create_signal(cx, 0u8);
+    let straighten = create_signal(cx, "0".to_string());
+    let auto_crop = create_signal(cx, true);
+    let aspect = create_signal(cx, AspectRatio::default().name().to_string());
+    let aspects = create_signal(cx, AspectRatio::ALL.to_vec());
+    let crop_left = create_signal(cx, "0".to_string());
+    let crop_top = create_signal(cx, "0".to_string());
+    let crop_right = create_signal(cx, "0".to_string());
+    let crop_bottom = create_signal(cx, "0".to_string());
+    let geometry = move || {
+        let [left, top, right, bottom] = [crop_left, crop_top, crop_right, crop_bottom]
+            .map(|v| v.get().parse::<f32>().unwrap_or(0.0) / 100.0);
+        Geometry {
+            rotate: *rotate.get(),
+            straighten: straighten.get().parse().unwrap_or(0.0),
+            auto_crop: *auto_crop.get(),
+            crop: if left + top + right + bottom > 0.0 {
+                Some(CropRect {
+                    x: left,
+                    y: top,
+                    width: (1.0 - left - right).max(0.01),
+                    height: (1.0 - top - bottom).max(0.01),
+                })
+            } else {
+                None
+            },
+            aspect: serde_json::from_value(serde_json::Value::String(aspect.get().to_string())).unwrap_or_default(),
+        }
+    };
+    let set_geometry = move |g: Geometry| {
+        rotate.set(g.rotate % 4);
+        straighten.set(g.straighten.to_string());
+        auto_crop.set(g.auto_crop);
+        aspect.set(g.aspect.name().to_string());
+        let crop = g.crop.unwrap_or(CropRect { x: 0.0, y: 0.0, width: 1.0, height: 1.0 });
+        crop_left.set(format!("{}", (crop.x * 100.0).round()));
+        crop_top.set(format!("{}", (crop.y * 100.0).round()));
+        crop_right.set(format!("{}", ((1.0 - crop.x - crop.width) * 100.0).round().max(0.0)));
+        crop_bottom.set(format!("{}", ((1.0 - crop.y - crop.height) * 100.0).round().max(0.0)));
+    };
+    let clipping_url = create_signal(cx, String::new());
 
-    wb_label.set("自动白平衡".to_string());
+    let loading = create_signal(cx, false);
 
     // 当前显示的图片索引
     let current_index = create_signal(cx, 0);
@@ -349,31 +525,31 @@ pub async fn Body<G: Html>(cx: Scope<'_>) -> View<G> {
                 .get::<DomNode>()
                 .unchecked_into::<HtmlOptionElement>()
                 .value();
+            let second_lut = second_lut_ref
+                .get::<DomNode>()
+                .unchecked_into::<HtmlOptionElement>()
+                .value();
             // let filename = raw_ref
             //     .get::<DomNode>()
             //     .unchecked_into::<HtmlOptionElement>()
             //     .value();
             let filename = images_list.get()[*current_index.get()].filename.clone();
             let image_id = images_list.get()[*current_index.get()].id.clone();
-            let wb = auto_wb_ref
-                .get::<DomNode>()
-                .unchecked_into::<HtmlInputElement>()
-                .checked();
-            let threshold_ = if *threshold_flag.get() {
-                -1
+            let denoise = if *threshold_flag.get() {
+                Denoise::Auto
             } else {
-                threshold.get().to_string().parse::<i32>().unwrap()
+                Denoise::Manual(threshold.get().to_string().parse::<f32>().unwrap())
             };
-            let exp_shift_ = if *exp_shift_flag.get() {
-                -3.0
+            let exposure = if *exp_shift_flag.get() {
+                Exposure::Auto
             } else {
-                exp_shift.get().to_string().parse::<f64>().unwrap()
+                Exposure::Manual(exp_shift.get().to_string().parse::<f32>().unwrap())
             };
 
             let exp_string_ = format!(
                 "lut: {} wb: {} exp_shift: {} threshold: {}",
-                lut,
-                if wb { "auto" } else { "camera" },
+                luts.get().iter().find(|x| x.0 == lut).map_or("No Lut", |x| x.1.as_str()),
+                wb_mode.get(),
                 if *exp_shift_flag.get() {
                     "auto".to_string()
                 } else {
@@ -386,23 +562,147 @@ pub async fn Body<G: Html>(cx: Scope<'_>) -> View<G> {
                 }
             );
             let filename_ = filename.clone();
-            img_url.set(
-                get_jpg(Parameters {
-                    id:image_id,
-                    filename,
-                    lut,
-                    wb,
-                    exp_shift: exp_shift_,
-                    threshold: threshold_,
-                },base_url_c.get().as_str())
-                .await,
-            );
+            let params = Parameters {
+                id:image_id,
+                filename,
+                lut_id: lut.parse().ok(),
+                lut_intensity: lut_intensity.get().parse().unwrap_or(100.0),
+                second_lut_id: second_lut.parse().ok(),
+                second_lut_intensity: second_lut_intensity.get().parse().unwrap_or(100.0),
+                lut_interpolation: serde_json::from_value(serde_json::Value::String(lut_interpolation.get().to_string())).unwrap_or_default(),
+                white_balance: white_balance(),
+                exposure,
+                denoise,
+                demosaic: serde_json::from_value(serde_json::Value::String(demosaic.get().to_string())).unwrap_or_default(),
+                highlight: match highlight.get().as_str() {
+                    "unclip" => Highlight::Unclip,
+                    "blend" => Highlight::Blend,
+                    "rebuild" => Highlight::Rebuild(rebuild_level.get().parse().unwrap_or(5)),
+                    _ => Highlight::Clip,
+                },
+                exp_preserve: exp_preserve.get().parse().unwrap_or(0.8),
+                lens_correction: lens_correction(),
+                sharpening: sharpening(),
+                geometry: Some(geometry()),
+            };
+            if *exp_shift_flag.get() {
+                auto_ev.set(get_exposure(params.clone(),base_url_c.get().as_str()).await);
+            }
+            img_url.set(get_jpg(params.clone(),base_url_c.get().as_str()).await);
+            clipping_url.set(if *clipping_flag.get() {
+                get_clipping(params,base_url_c.get().as_str()).await.unwrap_or_default()
+            } else {
+                String::new()
+            });
             file_name.set(filename_);
             exp_string.set(exp_string_);
             loading.set(false);
         })
     };
 
+    let pick_grey = move |e: web_sys::Event| {
+        if !*picking.get() {
+            return;
+        }
+        picking.set(false);
+        let e = e.unchecked_into::<MouseEvent>();
+        let preview = preview_ref.get::<DomNode>().unchecked_into::<HtmlElement>();
+        let x = e.offset_x() as f32 / preview.client_width().max(1) as f32;
+        let y = e.offset_y() as f32 / preview.client_height().max(1) as f32;
+        spawn_local_scoped(cx, async move {
+            loading.set(true);
+            let image = images_list.get()[*current_index.get()].clone();
+            let params = Parameters {
+                id: image.id,
+                filename: image.filename,
+                white_balance: WhiteBalance::GreyPoint { x, y },
+                geometry: Some(geometry()),
+                ..Default::default()
+            };
+            if let Some(mul) = get_wb(params, base_url_c.get().as_str()).await {
+                wb_mul.set(mul);
+                wb_mode.set("multipliers".to_string());
+            }
+            loading.set(false);
+        })
+    };
+
+    let meter = move |_| {
+        spawn_local_scoped(cx, async move {
+            let image = images_list.get()[*current_index.get()].clone();
+            let params = Parameters {
+                id: image.id,
+                filename: image.filename,
+                white_balance: white_balance(),
+                geometry: Some(geometry()),
+                ..Default::default()
+            };
+            auto_ev.set(get_exposure(params, base_url_c.get().as_str()).await);
+        })
+    };
+
+    // 以自动曝光的结果作为手动曝光补偿，之后可继续调整
+    let use_auto_ev = move |_| {
+        if let Some(ev) = *auto_ev.get() {
+            exp_shift.set(format!("{:.1}", ev));
+            exp_shift_flag.set(false);
+            exp_shift_ref.get::<DomNode>().unchecked_into::<HtmlInputElement>().set_checked(false);
+        }
+    };
+
+    // 将当前白平衡保存到用户默认参数，批量转换时使用
+    let save_wb = move |_| {
+        spawn_local_scoped(cx, async move {
+            let (mut user, _) = getuser(*user_id.get(), graphql_url_c.get().as_str()).await;
+            user.options = Some(set_option(user.options.as_deref().unwrap_or_default(), "white_balance", white_balance()));
+            updateuser(*user_id.get(), user, graphql_url_c.get().as_str()).await;
+        })
+    };
+
+    // 将 lut 强度、叠加的第二个 lut 和插值方式保存到用户默认参数，第一个 lut 在设置页面中选择
+    let save_lut = move |_| {
+        spawn_local_scoped(cx, async move {
+            let second_lut = second_lut_ref
+                .get::<DomNode>()
+                .unchecked_into::<HtmlOptionElement>()
+                .value();
+            let second_lut_id = second_lut.parse::<i32>().ok();
+            let (mut user, _) = getuser(*user_id.get(), graphql_url_c.get().as_str()).await;
+            let options = set_option(user.options.as_deref().unwrap_or_default(), "lut_intensity", lut_intensity.get().parse::<f32>().unwrap_or(100.0));
+            let options = set_option(&options, "second_lut_id", second_lut_id);
+            let options = set_option(&options, "second_lut_intensity", second_lut_intensity.get().parse::<f32>().unwrap_or(100.0));
+            let interpolation = serde_json::from_value::<LutInterpolation>(serde_json::Value::String(lut_interpolation.get().to_string())).unwrap_or_default();
+            user.options = Some(set_option(&options, "lut_interpolation", interpolation));
+            updateuser(*user_id.get(), user, graphql_url_c.get().as_str()).await;
+        })
+    };
+
+    // 将当前锐化参数保存到用户默认参数，批量转换时使用
+    let save_sharpening = move |_| {
+        spawn_local_scoped(cx, async move {
+            let (mut user, _) = getuser(*user_id.get(), graphql_url_c.get().as_str()).await;
+            user.options = Some(set_option(user.options.as_deref().unwrap_or_default(), "sharpening", sharpening()));
+            updateuser(*user_id.get(), user, graphql_url_c.get().as_str()).await;
+        })
+    };
+
+    // 将镜头校正参数保存到用户默认参数，批量转换时使用
+    let save_lens_correction = move |_| {
+        spawn_local_scoped(cx, async move {
+            let (mut user, _) = getuser(*user_id.get(), graphql_url_c.get().as_str()).await;
+            user.options = Some(set_option(user.options.as_deref().unwrap_or_default(), "lens_correction", lens_correction()));
+            updateuser(*user_id.get(), user, graphql_url_c.get().as_str()).await;
+        })
+    };
+
+    // 保存当前图像的几何变换参数，缓存图像会在下次扫描时重新生成
+    let save_image_geometry = move |_| {
+        spawn_local_scoped(cx, async move {
+            let image_id = images_list.get()[*current_index.get()].id;
+            save_geometry(image_id, geometry(), base_url_c.get().as_str()).await;
+        })
+    };
+
     let save =  move |_| {
         spawn_local_scoped(cx, async move {
             let url_file = img_url
@@ -561,9 +861,13 @@ pub async fn Body<G: Html>(cx: Scope<'_>) -> View<G> {
                                                 i(class="bx bx-wrench",style="margin-right: 20px;",on:click=move |_| {
                                                     current_index.set(index);
                                                     let _image = images_list.get()[index].clone();
+                                                    let image_id = _image.id;
                                                     is_edit.set(true);
                                                     file_name.set(_image.filename);
                                                     img_url.set(_image.url);
+                                                    spawn_local_scoped(cx, async move {
+                                                        set_geometry(get_geometry(image_id, base_url_c.get().as_str()).await.unwrap_or_default());
+                                                    });
                                                 })
                                                 i(class="bx bx-info-circle",on:click=move |_|{current_index.set(index);is_zoomed.set(true)})
                                                 
@@ -633,7 +937,16 @@ pub async fn Body<G: Html>(cx: Scope<'_>) -> View<G> {
             div(style="display: flex;justify-content: center;align-items: center;"){
                 article(){
                     header(){(file_name.get())}
-                    img(src = img_url.get())
+                    div(style="position: relative;"){
+                    img(ref=preview_ref,src = img_url.get(),style=if *picking.get() { "cursor: crosshair;" } else { "" },on:click=pick_grey)
+                    (if clipping_url.get().is_empty() {
+                        view! {cx, }
+                    } else {
+                        view! {cx,
+                            img(src = clipping_url.get(),style="position: absolute;top: 0;left: 0;width: 100%;height: 100%;pointer-events: none;opacity: 0.6;")
+                        }
+                    })
+                    }
                     footer(){small(){i(){(exp_string.get())}}}
                 }
                 }
@@ -652,18 +965,73 @@ pub async fn Body<G: Html>(cx: Scope<'_>) -> View<G> {
                                     )
                                 }
                         }
-                    input(ref=auto_wb_ref,type="checkbox",role="switch",checked=true,on:click = move |_| {
-                            let wb = auto_wb_ref.get::<DomNode>().unchecked_into::<HtmlInputElement>().checked();
-                            if wb{
-                                wb_label.set("自动白平衡".to_string());
+                    fieldset(class="grid"){
+                        input(bind:value=lut_intensity,type="range",min="0",max="100",step="1")
+                        label(){"强度 "(lut_intensity.get())"%"}
+                    }
+                    select(ref=second_lut_ref,aria-label="选择叠加的Lut"){
+                        option(selected=true,value="No Lut"){"不叠加 Lut"}
+                        Indexed(
+                            iterable=luts,
+                            view=|cx, x|
+                            view! {cx,
+                                option(value = x.0){(x.1)}
+                                },
+                            )
+                        }
+                    fieldset(class="grid"){
+                        input(bind:value=second_lut_intensity,type="range",min="0",max="100",step="1")
+                        label(){"强度 "(second_lut_intensity.get())"%"}
+                    }
+                    select(bind:value=lut_interpolation,aria-label="选择Lut插值方式"){
+                        Indexed(
+                            iterable=lut_interpolations,
+                            view=|cx, x|
+                            view! {cx,
+                                option(value = x.name()){(x.label())}
+                                },
+                            )
+                        }
+                    button(class="secondary",on:click=save_lut){"设为默认 Lut 参数"}
+                    }
+                    }
+
+                fieldset(){
+                    article(){
+                        header(){"白平衡"}
+                    select(bind:value=wb_mode,aria-label="选择白平衡"){
+                        option(value="auto"){"自动白平衡"}
+                        option(value="camera"){"相机白平衡"}
+                        option(value="temperature"){"色温"}
+                        option(value="multipliers"){"自定义倍率"}
+                    }
+                    (if *wb_mode.get() == "temperature" {
+                        view! {cx,
+                            fieldset(class="grid"){
+                                input(bind:value=kelvin,type="range",min="2000",max="12000",step="50")
+                                label(){(kelvin.get())"K"}
                             }
-                            else{
-                                wb_label.set("相机白平衡".to_string());
+                            fieldset(class="grid"){
+                                input(bind:value=tint,type="range",min="-150",max="150",step="1")
+                                label(){"色调 "(tint.get())}
                             }
-                        })
-                        label(){(wb_label.get())}
+                        }
+                    } else if *wb_mode.get() == "multipliers" {
+                        let mul = *wb_mul.get();
+                        view! {cx,
+                            small(){(format!("R {:.3} G {:.3} B {:.3} G2 {:.3}", mul[0], mul[1], mul[2], mul[3]))}
+                        }
+                    } else {
+                        view! {cx, }
+                    })
+                    fieldset(class="grid"){
+                        button(class="secondary",on:click=move |_| picking.set(true)){
+                            (if *picking.get() { "点击预览图中的中性灰" } else { "选取灰点" })
+                        }
+                        button(class="secondary",on:click=save_wb){"设为默认白平衡"}
                     }
                     }
+                }
 
 
                 fieldset(){
@@ -678,7 +1046,159 @@ pub async fn Body<G: Html>(cx: Scope<'_>) -> View<G> {
                         let vvaaw = vvvw.unchecked_into::<HtmlInputElement>().checked();
                         exp_shift_flag.set(vvaaw);
                     })"使用自动曝光补偿"
+                    fieldset(class="grid"){
+                        label(){(match *auto_ev.get() {
+                            Some(ev) => format!("自动曝光 {:+.1} EV", ev),
+                            None => "自动曝光 --".to_string(),
+                        })}
+                        button(class="secondary",on:click=meter){"测光"}
+                        button(class="secondary",disabled=auto_ev.get().is_none(),on:click=use_auto_ev){"以此为手动值"}
+                    }
+
+                    }
+                }
+
+                fieldset(){
+                    article(){
+                        header(){"高光恢复"}
+                    select(bind:value=highlight,aria-label="选择高光恢复模式"){
+                        option(value="clip"){"裁切"}
+                        option(value="unclip"){"不裁切"}
+                        option(value="blend"){"混合"}
+                        option(value="rebuild"){"重建"}
+                    }
+                    (if *highlight.get() == "rebuild" {
+                        view! {cx,
+                            fieldset(class="grid"){
+                                input(bind:value=rebuild_level,type="range",min="3",max="9",step="1")
+                                label(){"重建级别 "(rebuild_level.get())}
+                            }
+                        }
+                    } else {
+                        view! {cx, }
+                    })
+                    fieldset(class="grid"){
+                        input(bind:value=exp_preserve,type="range",min="0",max="1",step="0.05")
+                        label(){"高光保护 "(exp_preserve.get())}
+                    }
+                    label(){
+                        input(type="checkbox",role="switch",bind:checked=clipping_flag)
+                        "显示溢出警告"
+                    }
+                    }
+                }
 
+                fieldset(){
+                    article(){
+                        header(){"镜头校正"}
+                    label(){
+                        input(type="checkbox",role="switch",bind:checked=lens_profile)
+                        "使用镜头配置文件"
+                    }
+                    fieldset(class="grid"){
+                        input(bind:value=vignetting,type="range",min="-100",max="100",step="5")
+                        label(){"暗角 "(vignetting.get())}
+                    }
+                    fieldset(class="grid"){
+                        input(bind:value=distortion,type="range",min="-100",max="100",step="5")
+                        label(){"畸变 "(distortion.get())}
+                    }
+                    fieldset(class="grid"){
+                        input(bind:value=ca_red,type="range",min="0.99",max="1.01",step="0.0005")
+                        label(){"红色色差 "(ca_red.get())}
+                    }
+                    fieldset(class="grid"){
+                        input(bind:value=ca_blue,type="range",min="0.99",max="1.01",step="0.0005")
+                        label(){"蓝色色差 "(ca_blue.get())}
+                    }
+                    button(class="secondary",on:click=save_lens_correction){"设为默认镜头校正"}
+                    }
+                }
+
+                fieldset(){
+                    article(){
+                        header(){"裁切和旋转"}
+                    fieldset(class="grid"){
+                        button(class="secondary",on:click=move |_| rotate.set((*rotate.get() + 3) % 4)){i(class="bx bx-rotate-left")}
+                        button(class="secondary",on:click=move |_| rotate.set((*rotate.get() + 1) % 4)){i(class="bx bx-rotate-right")}
+                        label(){"旋转 "(*rotate.get() as u32 * 90)"°"}
+                    }
+                    fieldset(class="grid"){
+                        input(bind:value=straighten,type="range",min="-45",max="45",step="0.1")
+                        label(){"拉直 "(straighten.get())"°"}
+                    }
+                    label(){
+                        input(type="checkbox",role="switch",bind:checked=auto_crop)
+                        "自动裁掉拉直后的空白"
+                    }
+                    select(bind:value=aspect,aria-label="选择裁切比例"){
+                        Indexed(
+                            iterable=aspects,
+                            view=|cx, x|
+                            view! {cx,
+                                option(value = x.name()){(x.label())}
+                                },
+                            )
+                        }
+                    fieldset(class="grid"){
+                        input(bind:value=crop_left,type="range",min="0",max="45",step="1")
+                        label(){"左 "(crop_left.get())"%"}
+                    }
+                    fieldset(class="grid"){
+                        input(bind:value=crop_right,type="range",min="0",max="45",step="1")
+                        label(){"右 "(crop_right.get())"%"}
+                    }
+                    fieldset(class="grid"){
+                        input(bind:value=crop_top,type="range",min="0",max="45",step="1")
+                        label(){"上 "(crop_top.get())"%"}
+                    }
+                    fieldset(class="grid"){
+                        input(bind:value=crop_bottom,type="range",min="0",max="45",step="1")
+                        label(){"下 "(crop_bottom.get())"%"}
+                    }
+                    fieldset(class="grid"){
+                        button(class="secondary",on:click=move |_| set_geometry(Geometry::default())){"重置"}
+                        button(class="secondary",on:click=save_image_geometry){"保存到此图像"}
+                    }
+                    }
+                }
+
+                fieldset(){
+                    article(){
+                        header(){"锐化"}
+                    fieldset(class="grid"){
+                        input(bind:value=sharpen_amount,type="range",min="0",max="500",step="10")
+                        label(){"数量 "(sharpen_amount.get())"%"}
+                    }
+                    fieldset(class="grid"){
+                        input(bind:value=sharpen_radius,type="range",min="0.3",max="5",step="0.1")
+                        label(){"半径 "(sharpen_radius.get())}
+                    }
+                    fieldset(class="grid"){
+                        input(bind:value=sharpen_threshold,type="range",min="0",max="255",step="1")
+                        label(){"阈值 "(sharpen_threshold.get())}
+                    }
+                    fieldset(class="grid"){
+                        input(bind:value=clarity,type="range",min="-100",max="100",step="5")
+                        label(){"清晰度 "(clarity.get())}
+                    }
+                    button(class="secondary",on:click=save_sharpening){"设为默认锐化"}
+                    }
+                }
+
+                fieldset(){
+                    article(){
+                        header(){"去马赛克算法"}
+                    select(bind:value=demosaic,aria-label="选择去马赛克算法"){
+                        Indexed(
+                            iterable=demosaics,
+                            view=|cx, x|
+                            view! {cx,
+                                option(value = x.name()){(x.label())}
+                                },
+                            )
+                        }
+                    small(){"半尺寸预览不进行插值，仅影响原尺寸输出"}
                     }
                 }
 
@@ -54,8 +54,12 @@ use update_user::UserInput;
 use create_storage::StorageInput;
 
 use crate::{pages::home::{luts_query, LutsQuery}, User};
+use crate::options::{
+    get_option, set_option, ColorSpace, Demosaic, Orientation, OutputFormat, ToneMapOperator, ToneMapping,
+    WhiteBalance,
+};
 
-async fn getuser(user_id:i32,url: &str) -> (UserInput,Vec<(usize,Storage)>) {
+pub(crate) async fn getuser(user_id:i32,url: &str) -> (UserInput,Vec<(usize,Storage)>) {
     // let base_url = web_sys::window().unwrap().location().origin().unwrap();
     // let url = format!("{}/api/graphql", base_url);
     // let url = format!("http://127.0.0.1:8081/api/graphql");
@@ -92,12 +96,13 @@ async fn getuser(user_id:i32,url: &str) -> (UserInput,Vec<(usize,Storage)>) {
         half_size:response_data.user.half_size.clone(),
         quality:response_data.user.quality.clone(),
         lut_id:response_data.user.lut_id.clone(),
+        options:Some(response_data.user.options.clone()),
         password: "".to_string(),
     };
     (user,stoarges)
 }
 
-async fn updateuser(user_id:i32,user:UserInput, url:&str) {
+pub(crate) async fn updateuser(user_id:i32,user:UserInput, url:&str) {
     // let base_url = web_sys::window().unwrap().location().origin().unwrap();
     // let base_url = "http://127.0.0.1:8081";
     let client = reqwest::Client::new();
@@ -185,13 +190,48 @@ pub async fn Body<G: Html>(cx: Scope<'_>) -> View<G> {
     
     let lut_ref = create_node_ref(cx);
 
+    let format_ref = create_node_ref(cx);
+    let format = get_option::<OutputFormat>(user.get().options.as_deref().unwrap_or_default(), "format");
+    let formats = create_signal(cx, OutputFormat::ALL.to_vec());
+
+    let orientation_ref = create_node_ref(cx);
+
+    let color_space_ref = create_node_ref(cx);
+    let color_space = get_option::<ColorSpace>(user.get().options.as_deref().unwrap_or_default(), "color_space");
+    let color_spaces = create_signal(cx, ColorSpace::ALL.to_vec());
+
+    let demosaic_ref = create_node_ref(cx);
+    let demosaic = get_option::<Demosaic>(user.get().options.as_deref().unwrap_or_default(), "demosaic");
+    let demosaics = create_signal(cx, Demosaic::ALL.to_vec());
+
+    let tone_map_ref = create_node_ref(cx);
+    let tone_mapping = get_option::<ToneMapping>(user.get().options.as_deref().unwrap_or_default(), "tone_mapping");
+    let tone_maps = create_signal(cx, ToneMapOperator::ALL.to_vec());
+    let tone_shift = create_signal(cx, tone_mapping.shift.to_string());
+    let tone_smooth = create_signal(cx, tone_mapping.smooth.to_string());
+
+    // 编辑器中保存的手动白平衡，批量转换时优先于自动/相机白平衡开关
+    let manual_wb = !matches!(
+        get_option::<WhiteBalance>(user.get().options.as_deref().unwrap_or_default(), "white_balance"),
+        WhiteBalance::Auto | WhiteBalance::Camera
+    );
+    let keep_wb_ref = create_node_ref(cx);
+    let orientation = get_option::<Orientation>(user.get().options.as_deref().unwrap_or_default(), "orientation");
+
     
 
     let luts = create_signal(cx, getluts(&graphql_url).await);
+    // lut 强度（%）和叠加的第二个 lut，第二个 lut 以编号保存在 options 中
+    let lut_intensity = create_signal(cx, get_option::<Option<f32>>(user.get().options.as_deref().unwrap_or_default(), "lut_intensity").unwrap_or(100.0).to_string());
+    let second_lut_ref = create_node_ref(cx);
+    let second_lut_id = get_option::<Option<i32>>(user.get().options.as_deref().unwrap_or_default(), "second_lut_id");
+    let second_lut_intensity = create_signal(cx, get_option::<Option<f32>>(user.get().options.as_deref().unwrap_or_default(), "second_lut_intensity").unwrap_or(100.0).to_string());
 
     let upfile_ref = create_node_ref(cx);
 
     let loading = create_signal(cx, false);
+    // 上传结果，LUT 无效时显示服务端返回的错误
+    let upload_msg = create_signal(cx, String::new());
 
     let base_url_c = create_signal(cx,base_url);
     let graphql_url_c = create_signal(cx,graphql_url);
@@ -233,6 +273,57 @@ pub async fn Body<G: Html>(cx: Scope<'_>) -> View<G> {
                 .get::<DomNode>()
                 .unchecked_into::<HtmlInputElement>()
                 .checked();
+            let format_name = format_ref
+                .get::<DomNode>()
+                .unchecked_into::<HtmlOptionElement>()
+                .value();
+            let format = serde_json::from_value::<OutputFormat>(serde_json::Value::String(format_name)).unwrap_or_default();
+            let options = set_option(user.get().options.as_deref().unwrap_or_default(), "format", format);
+            let orientation_name = orientation_ref
+                .get::<DomNode>()
+                .unchecked_into::<HtmlOptionElement>()
+                .value();
+            let orientation = serde_json::from_value::<Orientation>(serde_json::Value::String(orientation_name)).unwrap_or_default();
+            let options = set_option(&options, "orientation", orientation);
+            let color_space_name = color_space_ref
+                .get::<DomNode>()
+                .unchecked_into::<HtmlOptionElement>()
+                .value();
+            let color_space = serde_json::from_value::<ColorSpace>(serde_json::Value::String(color_space_name)).unwrap_or_default();
+            let options = set_option(&options, "color_space", color_space);
+            let demosaic_name = demosaic_ref
+                .get::<DomNode>()
+                .unchecked_into::<HtmlOptionElement>()
+                .value();
+            let demosaic = serde_json::from_value::<Demosaic>(serde_json::Value::String(demosaic_name)).unwrap_or_default();
+            let options = set_option(&options, "demosaic", demosaic);
+            let tone_map_name = tone_map_ref
+                .get::<DomNode>()
+                .unchecked_into::<HtmlOptionElement>()
+                .value();
+            let tone_mapping = ToneMapping {
+                operator: serde_json::from_value::<ToneMapOperator>(serde_json::Value::String(tone_map_name)).unwrap_or_default(),
+                shift: tone_shift.get().parse().unwrap_or(1.0),
+                smooth: tone_smooth.get().parse().unwrap_or(0.9),
+            };
+            let options = set_option(&options, "tone_mapping", tone_mapping);
+            let second_lut = second_lut_ref
+                .get::<DomNode>()
+                .unchecked_into::<HtmlOptionElement>()
+                .value();
+            let options = set_option(&options, "lut_intensity", lut_intensity.get().parse::<f32>().unwrap_or(100.0));
+            let options = set_option(&options, "second_lut_id", second_lut.parse::<i32>().ok());
+            let options = set_option(&options, "second_lut_intensity", second_lut_intensity.get().parse::<f32>().unwrap_or(100.0));
+            let keep_wb = manual_wb
+                && keep_wb_ref
+                    .get::<DomNode>()
+                    .unchecked_into::<HtmlInputElement>()
+                    .checked();
+            let options = if manual_wb && !keep_wb {
+                set_option(&options, "white_balance", WhiteBalance::Camera)
+            } else {
+                options
+            };
             // user.set(value)
             let q = quality.get().clone();
             let _user = UserInput{
@@ -243,6 +334,7 @@ pub async fn Body<G: Html>(cx: Scope<'_>) -> View<G> {
                 wb:wb,
                 half_size:hf,
                 quality:q.parse::<i64>().unwrap(),
+                options:Some(options),
             };
             updateuser(*user_id.get(), _user,graphql_url_c.get().as_str()).await;
         })
@@ -281,7 +373,7 @@ pub async fn Body<G: Html>(cx: Scope<'_>) -> View<G> {
             article(){
                 header(){"上传Lut"}
                 fieldset(role="group"){
-                    input(ref=upfile_ref,type="file",id="file",name="file")
+                    input(ref=upfile_ref,type="file",id="file",name="file",accept=".cube,.3dl,.csp,.png,.tif,.tiff")
         
                     button(aria-busy=*loading.get(),on:click = move|_|{
                         spawn_local_scoped(cx, async move {
@@ -299,17 +391,23 @@ pub async fn Body<G: Html>(cx: Scope<'_>) -> View<G> {
                             let file_part = reqwest::multipart::Part::bytes(file_bytes).file_name(file_name.clone());
                             let form = reqwest::multipart::Form::new().part("file",file_part);
                             let client = reqwest::Client::new();
-                            client.post(up_url)
+                            let res = client.post(up_url)
                                 .multipart(form)
                                 .send()
                                 .await
                                 .expect("Failed to send request");
+                            upload_msg.set(if res.status().is_success() {
+                                format!("{} 上传成功", file_name)
+                            } else {
+                                res.text().await.unwrap_or_default()
+                            });
                             // images.set(getrawfiles(*user_id.get(),graphql_url_c.get().as_str()).await);
                             // log::info!("{:?}",images);
-                            // loading.set(false);
+                            loading.set(false);
         
                     })}){"submit"}
                 }
+                small(){(upload_msg.get())}
         
             }
 
@@ -328,6 +426,14 @@ pub async fn Body<G: Html>(cx: Scope<'_>) -> View<G> {
                     }
                 })
                 label(){(wb_label.get())}
+                (if manual_wb {
+                    view! {cx,
+                        input(ref=keep_wb_ref,type="checkbox",role="switch",checked=true)
+                        label(){"使用编辑器保存的白平衡"}
+                    }
+                } else {
+                    view! {cx, }
+                })
             }
 
                 fieldset(){
@@ -359,6 +465,90 @@ pub async fn Body<G: Html>(cx: Scope<'_>) -> View<G> {
                             },
                         )
                     }
+                    fieldset(class="grid"){
+                    input(bind:value=lut_intensity,type="range",min="0",max="100",step="1")
+                    label(){"强度 " (lut_intensity.get()) "%"}
+                    }
+                }
+                fieldset(){
+                legend(){"叠加 Lut"}
+                select(ref=second_lut_ref,aria-label="选择叠加的Lut"){
+                    option(value=""){"不叠加 Lut"}
+                    Indexed(
+                        iterable=luts,
+                        view=move |cx, x|
+                        view! {cx,
+                            option(value = x.0,selected = second_lut_id == Some(x.0 as i32)){(x.1)}
+                            },
+                        )
+                    }
+                    fieldset(class="grid"){
+                    input(bind:value=second_lut_intensity,type="range",min="0",max="100",step="1")
+                    label(){"强度 " (second_lut_intensity.get()) "%"}
+                    }
+                }
+                fieldset(){
+                legend(){"输出格式"}
+                select(ref=format_ref,aria-label="选择输出格式"){
+                    Indexed(
+                        iterable=formats,
+                        view=move |cx, x|
+                        view! {cx,
+                            option(value = x.name(),selected = x == format){(x.label())}
+                            },
+                        )
+                    }
+                }
+                fieldset(){
+                legend(){"输出色彩空间"}
+                select(ref=color_space_ref,aria-label="选择输出色彩空间"){
+                    Indexed(
+                        iterable=color_spaces,
+                        view=move |cx, x|
+                        view! {cx,
+                            option(value = x.name(),selected = x == color_space){(x.label())}
+                            },
+                        )
+                    }
+                }
+                fieldset(){
+                legend(){"去马赛克算法"}
+                select(ref=demosaic_ref,aria-label="选择去马赛克算法"){
+                    Indexed(
+                        iterable=demosaics,
+                        view=move |cx, x|
+                        view! {cx,
+                            option(value = x.name(),selected = x == demosaic){(x.label())}
+                            },
+                        )
+                    }
+                }
+                fieldset(){
+                legend(){"色调映射"}
+                select(ref=tone_map_ref,aria-label="选择色调映射算子"){
+                    Indexed(
+                        iterable=tone_maps,
+                        view=move |cx, x|
+                        view! {cx,
+                            option(value = x.name(),selected = x == tone_mapping.operator){(x.label())}
+                            },
+                        )
+                    }
+                    fieldset(class="grid"){
+                    input(bind:value=tone_shift,type="range",min="0.1",max="8",step="0.1")
+                    label(){"倍率 " (tone_shift.get())}
+                    }
+                    fieldset(class="grid"){
+                    input(bind:value=tone_smooth,type="range",min="0",max="1",step="0.05")
+                    label(){"高光平滑 " (tone_smooth.get())}
+                    }
+                }
+                fieldset(){
+                legend(){"图像方向"}
+                select(ref=orientation_ref,aria-label="选择图像方向处理方式"){
+                    option(value="rotate",selected = orientation == Orientation::Rotate){"旋转像素"}
+                    option(value="tag",selected = orientation == Orientation::Tag){"写入 exif 方向标签"}
+                    }
                 }
                 fieldset(){
                 legend(){"转换质量"}